@@ -0,0 +1,204 @@
+//! An opt-in REST API for driving playback from other devices and scripts.
+//!
+//! Everything lives under `/api/v1` and, apart from the schema, needs an
+//! `Authorization: Bearer <api_token>` header. `GET /api/v1/schema` describes
+//! the requests and responses as JSON schema.
+
+pub mod party;
+pub mod search;
+
+use std::{net::AddrParseError, result, sync::Arc};
+
+use controller::{Action, Controller};
+use controller::status::Status;
+use rofify::config::Config;
+use rofify::menu::MenuProgram;
+use rocket::{catch, catchers, get, post, routes, Request, State};
+use rocket::http::{ContentType, Status as HttpStatus};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::json::Json;
+use rocket::tokio::sync::Mutex;
+use rspotify::AuthCodePkceSpotify;
+use rspotify::model::SearchType;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+
+use search::SearchItem;
+
+const SCHEMA: &str = include_str!("schema.json");
+const DEFAULT_SEARCH_LIMIT: u32 = 20;
+const MAX_SEARCH_LIMIT: u32 = 50;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Set api_token in the config to use the API.")]
+    NoToken,
+    #[error("Invalid api_address: {0}")]
+    Address(#[from] AddrParseError),
+    #[error("Server error: {0}")]
+    Rocket(#[from] Box<rocket::Error>),
+    #[error("Party: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Party: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Controller(#[from] controller::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+struct ApiState {
+    client: Arc<AuthCodePkceSpotify>,
+    controller: Mutex<Controller>,
+    token: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct OkBody {
+    ok: bool,
+}
+
+#[derive(Deserialize)]
+struct QueueRequest {
+    uri: String,
+}
+
+type ApiResult<T> = result::Result<Json<T>, (HttpStatus, Json<ErrorBody>)>;
+
+fn api_error(status: HttpStatus, error: impl ToString) -> (HttpStatus, Json<ErrorBody>) {
+    (status, Json(ErrorBody { error: error.to_string() }))
+}
+
+fn ok() -> ApiResult<OkBody> {
+    Ok(Json(OkBody { ok: true }))
+}
+
+/// Parses a kebab/snake case name the way serde would from JSON, so the API
+/// accepts exactly the names its responses use.
+fn parse_name<T: DeserializeOwned>(name: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+/// Guards routes behind the configured bearer token.
+struct Authorized;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Authorized {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        let expected = match request.rocket().state::<ApiState>() {
+            Some(state) => &state.token,
+            None => return Outcome::Failure((HttpStatus::InternalServerError, ())),
+        };
+        let given = request
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "));
+
+        match given {
+            Some(given) if tokens_match(given, expected) => Outcome::Success(Authorized),
+            _ => Outcome::Failure((HttpStatus::Unauthorized, ())),
+        }
+    }
+}
+
+/// Compares without bailing at the first difference, so response times
+/// don't give away how much of a guess was right.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+#[get("/status")]
+async fn status(_authorized: Authorized, state: &State<ApiState>) -> ApiResult<Option<Status>> {
+    Status::fetch(&state.client)
+        .await
+        .map(Json)
+        .map_err(|error| api_error(HttpStatus::BadGateway, error))
+}
+
+#[post("/control/<action>")]
+async fn control(_authorized: Authorized, action: &str, state: &State<ApiState>) -> ApiResult<OkBody> {
+    let action: Action = parse_name(action)
+        .ok_or_else(|| api_error(HttpStatus::NotFound, format!("Unknown action {action:?}")))?;
+
+    state.controller
+        .lock()
+        .await
+        .act(&action)
+        .await
+        .map_err(|error| api_error(HttpStatus::BadGateway, error))?;
+    ok()
+}
+
+#[get("/search?<q>&<kind>&<limit>")]
+async fn find(
+    _authorized: Authorized,
+    q: &str,
+    kind: Option<&str>,
+    limit: Option<u32>,
+    state: &State<ApiState>
+) -> ApiResult<Vec<SearchItem>> {
+    let search_type = match kind {
+        Some(kind) => parse_name(kind)
+            .ok_or_else(|| api_error(HttpStatus::BadRequest, format!("Unknown kind {kind:?}")))?,
+        None => SearchType::Track,
+    };
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+
+    search::search(&state.client, q, search_type, limit)
+        .await
+        .map(Json)
+        .map_err(|error| api_error(HttpStatus::BadGateway, error))
+}
+
+#[post("/queue", data = "<item>")]
+async fn queue(_authorized: Authorized, item: Json<QueueRequest>, state: &State<ApiState>) -> ApiResult<OkBody> {
+    state.controller
+        .lock()
+        .await
+        .queue_uri(&item.uri)
+        .await
+        .map_err(|error| api_error(HttpStatus::BadGateway, error))?;
+    ok()
+}
+
+#[get("/schema")]
+fn schema() -> (ContentType, &'static str) {
+    (ContentType::JSON, SCHEMA)
+}
+
+#[catch(default)]
+fn default_catcher(status: HttpStatus, _request: &Request) -> Json<ErrorBody> {
+    Json(ErrorBody { error: status.reason_lossy().to_string() })
+}
+
+/// Serves the API on the configured address and port until interrupted.
+pub async fn run(client: Arc<AuthCodePkceSpotify>, program: MenuProgram, config: &Config) -> Result<()> {
+    let token = config.api_token.clone().ok_or(Error::NoToken)?;
+    let rocket_config = rocket::Config {
+        address: config.api_address.as_deref().unwrap_or_default().parse()?,
+        port: config.api_port.unwrap(),
+        ..Default::default()
+    };
+    let controller = Mutex::new(Controller::new(Arc::clone(&client), program).await?);
+
+    let _ = rocket::custom(&rocket_config)
+        .manage(ApiState { client, controller, token })
+        .mount("/api/v1", routes![status, control, find, queue, schema])
+        .register("/", catchers![default_catcher])
+        .launch()
+        .await
+        .map_err(Box::new)?;
+
+    Ok(())
+}
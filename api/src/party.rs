@@ -0,0 +1,319 @@
+//! Party mode: guests on the LAN search for tracks and suggest them from a
+//! web page, and the host approves or rejects each one from a menu before it
+//! goes into the queue.
+//!
+//! The host's routes only answer requests from this machine, which is how
+//! `rofify party review` talks to a running `rofify party serve`.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use controller::Controller;
+use notify::{notify, enotify_error};
+use rofify::config::Config;
+use rofify::menu::{Menu, MenuProgram, MenuResult, selection_index};
+use rocket::{get, post, routes, catchers, Request, State};
+use rocket::http::Status as HttpStatus;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use rspotify::AuthCodePkceSpotify;
+use rspotify::model::{SearchType, TrackId};
+use serde::{Serialize, Deserialize};
+
+use crate::{ApiResult, OkBody, Result, api_error, default_catcher, ok};
+use crate::search::{self, SearchItem};
+
+const PAGE: &str = include_str!("party.html");
+const SEARCH_LIMIT: u32 = 10;
+/// How long each guest has to wait between searches, to keep a crowd of
+/// phones from running into spotify's rate limits.
+const TRACK_URI_PREFIX: &str = "spotify:track:";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Suggestion {
+    pub id: u64,
+    pub uri: String,
+    pub name: String,
+    pub detail: String,
+    pub guest: String,
+}
+
+/// What guests send; the name and artists shown to the host are looked up
+/// from the URI, so guests can't label a track as something else.
+#[derive(Deserialize)]
+struct SuggestRequest {
+    uri: String,
+    guest: String,
+}
+
+struct Party {
+    client: Arc<AuthCodePkceSpotify>,
+    controller: Controller,
+    max_pending: usize,
+    cooldown: Duration,
+    search_cooldown: Duration,
+    suggestions: Mutex<Suggestions>,
+}
+
+#[derive(Default)]
+struct Suggestions {
+    pending: Vec<(IpAddr, Suggestion)>,
+    next_id: u64,
+    suggested_at: HashMap<IpAddr, Instant>,
+    searched_at: HashMap<IpAddr, Instant>,
+}
+
+/// Only lets through requests from this machine.
+struct Host;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Host {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+        match request.remote() {
+            Some(address) if address.ip().is_loopback() => Outcome::Success(Host),
+            _ => Outcome::Failure((HttpStatus::Forbidden, ())),
+        }
+    }
+}
+
+#[get("/")]
+fn page() -> RawHtml<&'static str> {
+    RawHtml(PAGE)
+}
+
+#[get("/search?<q>")]
+async fn find(q: &str, remote: SocketAddr, party: &State<Party>) -> ApiResult<Vec<SearchItem>> {
+    {
+        let mut suggestions = party.suggestions.lock().unwrap();
+        let searched_at = suggestions.searched_at.get(&remote.ip());
+
+        if searched_at.is_some_and(|at| at.elapsed() < party.search_cooldown) {
+            return Err(api_error(HttpStatus::TooManyRequests, "Slow down a little."));
+        }
+        suggestions.searched_at.insert(remote.ip(), Instant::now());
+    }
+
+    search::search(&party.client, q, SearchType::Track, SEARCH_LIMIT)
+        .await
+        .map(Json)
+        .map_err(|error| api_error(HttpStatus::BadGateway, error))
+}
+
+#[post("/suggest", data = "<request>")]
+async fn suggest(request: Json<SuggestRequest>, remote: SocketAddr, party: &State<Party>) -> ApiResult<OkBody> {
+    let id = match TrackId::from_uri(&request.uri) {
+        Ok(id) if request.uri.starts_with(TRACK_URI_PREFIX) => id,
+        _ => return Err(api_error(HttpStatus::BadRequest, "Only tracks can be suggested.")),
+    };
+
+    let ip = remote.ip();
+    // checked before the lookup too, so guests over their limits don't cost
+    // requests to spotify
+    check_limits(&party.suggestions.lock().unwrap(), ip, party)?;
+
+    let track = search::track(&party.client, id)
+        .await
+        .map_err(|error| api_error(HttpStatus::BadGateway, error))?;
+
+    let mut suggestions = party.suggestions.lock().unwrap();
+    check_limits(&suggestions, ip, party)?;
+
+    // control characters would break the host's menu, a row per line
+    let guest: String = request.guest.chars().filter(|ch| !ch.is_control()).collect();
+    let guest = match guest.trim() {
+        "" => ip.to_string(),
+        name => name.to_string(),
+    };
+    let suggestion = Suggestion {
+        id: suggestions.next_id,
+        uri: track.uri,
+        name: track.name,
+        detail: track.detail,
+        guest,
+    };
+
+    suggestions.next_id += 1;
+    suggestions.suggested_at.insert(ip, Instant::now());
+    suggestions.pending.push((ip, suggestion));
+    ok()
+}
+
+/// Turns away guests still waiting out their cooldown or with too many
+/// suggestions pending.
+fn check_limits(suggestions: &Suggestions, ip: IpAddr, party: &Party) -> std::result::Result<(), (HttpStatus, Json<crate::ErrorBody>)> {
+    if let Some(at) = suggestions.suggested_at.get(&ip) {
+        let wait = party.cooldown.saturating_sub(at.elapsed());
+        if !wait.is_zero() {
+            return Err(api_error(
+                HttpStatus::TooManyRequests,
+                format!("You can suggest another song in {} seconds.", wait.as_secs() + 1)
+            ));
+        }
+    }
+
+    let pending = suggestions.pending.iter().filter(|(guest_ip, _)| *guest_ip == ip).count();
+    if pending >= party.max_pending {
+        return Err(api_error(
+            HttpStatus::TooManyRequests,
+            "The host hasn't got through your other suggestions yet."
+        ));
+    }
+    Ok(())
+}
+
+#[get("/host/pending")]
+fn pending(_host: Host, party: &State<Party>) -> Json<Vec<Suggestion>> {
+    let suggestions = party.suggestions.lock().unwrap();
+    Json(suggestions.pending.iter().map(|(_, suggestion)| suggestion.clone()).collect())
+}
+
+#[post("/host/pending/<id>/approve")]
+async fn approve(_host: Host, id: u64, party: &State<Party>) -> ApiResult<OkBody> {
+    let (ip, suggestion) = take_pending(party, id)?;
+
+    match party.controller.queue_uri(&suggestion.uri).await {
+        Ok(_) => ok(),
+        Err(error) => {
+            // leave it for the host to try again
+            party.suggestions.lock().unwrap().pending.push((ip, suggestion));
+            Err(api_error(HttpStatus::BadGateway, error))
+        }
+    }
+}
+
+#[post("/host/pending/<id>/reject")]
+fn reject(_host: Host, id: u64, party: &State<Party>) -> ApiResult<OkBody> {
+    take_pending(party, id)?;
+    ok()
+}
+
+fn take_pending(party: &Party, id: u64) -> std::result::Result<(IpAddr, Suggestion), (HttpStatus, Json<crate::ErrorBody>)> {
+    let mut suggestions = party.suggestions.lock().unwrap();
+
+    match suggestions.pending.iter().position(|(_, suggestion)| suggestion.id == id) {
+        Some(index) => Ok(suggestions.pending.remove(index)),
+        None => Err(api_error(HttpStatus::NotFound, format!("No pending suggestion {id}"))),
+    }
+}
+
+/// Serves the guest page on the configured party address and port until
+/// interrupted.
+pub async fn serve(client: Arc<AuthCodePkceSpotify>, program: MenuProgram, config: &Config) -> Result<()> {
+    let rocket_config = rocket::Config {
+        address: config.party_address.as_deref().unwrap_or_default().parse()?,
+        port: config.party_port.unwrap(),
+        ..Default::default()
+    };
+    let party = Party {
+        controller: Controller::new(Arc::clone(&client), program).await?,
+        client,
+        max_pending: config.party_max_pending.unwrap(),
+        cooldown: Duration::from_secs(config.party_cooldown_secs.unwrap()),
+        search_cooldown: Duration::from_secs(config.party_search_cooldown_secs.unwrap()),
+        suggestions: Mutex::new(Suggestions::default()),
+    };
+
+    let _ = rocket::custom(&rocket_config)
+        .manage(party)
+        .mount("/", routes![page, find, suggest, pending, approve, reject])
+        .register("/", catchers![default_catcher])
+        .launch()
+        .await
+        .map_err(Box::new)?;
+
+    Ok(())
+}
+
+/// Goes through the pending suggestions of a running party with the host,
+/// until they run out or the host closes the menu.
+pub async fn review(config: &Config, program: MenuProgram) -> Result<()> {
+    let host_url = format!("http://127.0.0.1:{}/host/pending", config.party_port.unwrap());
+    let http = reqwest::Client::new();
+
+    loop {
+        let response = http.get(&host_url).send().await?.error_for_status()?;
+        let pending: Vec<Suggestion> = serde_json::from_str(&response.text().await?)?;
+
+        if pending.is_empty() {
+            notify("Party", "No suggestions waiting.", None);
+            return Ok(());
+        }
+
+        let menu = PendingMenu { http: http.clone(), host_url: host_url.clone(), pending };
+        match menu.select(program.clone()).await {
+            // a decision was made, so show what's left
+            MenuResult::Back => continue,
+            _ => return Ok(()),
+        }
+    }
+}
+
+struct PendingMenu {
+    http: reqwest::Client,
+    host_url: String,
+    pending: Vec<Suggestion>,
+}
+
+impl PendingMenu {
+    async fn decide(&self, suggestion: &Suggestion, decision: &str) -> Result<()> {
+        self.http
+            .post(format!("{}/{}/{decision}", self.host_url, suggestion.id))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Menu for PendingMenu {
+    fn items(&self) -> Vec<String> {
+        self.pending
+            .iter()
+            .enumerate()
+            .map(|(i, suggestion)| format!("{}: {} | {} | from {}", i, suggestion.name, suggestion.detail, suggestion.guest))
+            .collect()
+    }
+
+    async fn select(&self, program: MenuProgram) -> MenuResult {
+        let selection = self.prompt(program.clone(), "Suggestions");
+        let suggestion = match selection_index(&selection).ok().and_then(|index| self.pending.get(index)) {
+            Some(suggestion) => suggestion,
+            None => return MenuResult::Exit,
+        };
+
+        let decision = DecisionMenu.prompt(program, &suggestion.name);
+        let result = match decision.as_str() {
+            "Approve" => self.decide(suggestion, "approve").await,
+            "Reject" => self.decide(suggestion, "reject").await,
+            _ => return MenuResult::Back,
+        };
+
+        match result {
+            Ok(_) => if decision == "Approve" {
+                notify("Added to queue:", &format!("{} | {}", suggestion.name, suggestion.detail), None)
+            },
+            Err(error) => enotify_error(&format!("Failed to {} {}", decision.to_lowercase(), suggestion.name), &error),
+        }
+        MenuResult::Back
+    }
+}
+
+struct DecisionMenu;
+
+#[async_trait]
+impl Menu for DecisionMenu {
+    fn items(&self) -> Vec<String> {
+        vec!["Approve".to_string(), "Reject".to_string()]
+    }
+
+    async fn select(&self, program: MenuProgram) -> MenuResult {
+        MenuResult::Input(self.prompt(program, "Decision"))
+    }
+}
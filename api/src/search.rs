@@ -0,0 +1,92 @@
+use rspotify::{AuthCodePkceSpotify, ClientResult};
+use rspotify::model::{SearchResult, SearchType, TrackId};
+use rspotify::prelude::*;
+use serde::Serialize;
+
+/// One search result, flattened to what a remote needs to show and play it.
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchItem {
+    pub uri: String,
+    pub name: String,
+    /// Artists, owner or show, depending on the kind of item.
+    pub detail: String,
+}
+
+pub async fn search(
+    client: &AuthCodePkceSpotify,
+    query: &str,
+    search_type: SearchType,
+    limit: u32
+) -> ClientResult<Vec<SearchItem>> {
+    let result = client.search(query, search_type, None, None, Some(limit), None).await?;
+
+    let items = match result {
+        SearchResult::Tracks(page) => page.items
+            .into_iter()
+            .filter_map(|track| Some(SearchItem {
+                uri: track.id?.uri(),
+                name: track.name,
+                detail: join_names(track.artists.into_iter().map(|artist| artist.name)),
+            }))
+            .collect(),
+        SearchResult::Albums(page) => page.items
+            .into_iter()
+            .filter_map(|album| Some(SearchItem {
+                uri: album.id?.uri(),
+                name: album.name,
+                detail: join_names(album.artists.into_iter().map(|artist| artist.name)),
+            }))
+            .collect(),
+        SearchResult::Artists(page) => page.items
+            .into_iter()
+            .map(|artist| SearchItem {
+                uri: artist.id.uri(),
+                name: artist.name,
+                detail: String::new(),
+            })
+            .collect(),
+        SearchResult::Playlists(page) => page.items
+            .into_iter()
+            .map(|playlist| SearchItem {
+                uri: playlist.id.uri(),
+                name: playlist.name,
+                detail: playlist.owner.display_name.unwrap_or_default(),
+            })
+            .collect(),
+        SearchResult::Shows(page) => page.items
+            .into_iter()
+            .map(|show| SearchItem {
+                uri: show.id.uri(),
+                name: show.name,
+                detail: show.publisher,
+            })
+            .collect(),
+        SearchResult::Episodes(page) => page.items
+            .into_iter()
+            .map(|episode| SearchItem {
+                uri: episode.id.uri(),
+                name: episode.name,
+                detail: String::new(),
+            })
+            .collect(),
+    };
+
+    Ok(items)
+}
+
+/// The track `id` is, looked up on spotify rather than taken from whoever
+/// asked.
+pub async fn track(client: &AuthCodePkceSpotify, id: TrackId<'_>) -> ClientResult<SearchItem> {
+    let uri = id.uri();
+    let track = client.track(id, None).await?;
+
+    Ok(SearchItem {
+        uri,
+        name: track.name,
+        detail: join_names(track.artists.into_iter().map(|artist| artist.name)),
+    })
+}
+
+fn join_names(names: impl Iterator<Item = String>) -> String {
+    names.collect::<Vec<_>>().join(", ")
+}
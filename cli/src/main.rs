@@ -1,11 +1,25 @@
-use clap::{Parser, Subcommand};
-use notify::enotify;
-use std::{sync::Arc, process::exit};
-use rofify::{auth, config::Config};
+use api::party;
+use clap::{ArgGroup, Parser, Subcommand};
+use controller::bar::{self, BarOptions, BarStyle};
+use controller::status::{self, Status};
+use controller::watch;
+use controller::now_playing;
+use notify::{enotify, enotify_error, error_chain};
+use daemon::protocol::Reply;
+use std::{env, error::Error, fs, io, path::PathBuf, sync::Arc, process::{Command, exit}};
+use rofify::{auth, config::{self, Config}};
+use serde_yaml::Value;
+
+const DEFAULT_EDITOR: &str = "vi";
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
+    /// Config file to use instead of $ROFIFY_CONFIG or the default. Fields
+    /// can still be overridden with ROFIFY_<FIELD> variables. Commands a
+    /// running daemon handles use the daemon's config.
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
@@ -17,24 +31,129 @@ enum Commands {
     #[command(subcommand)]
         action: controller::Action,
     },
+    Status {
+        /// Template with {title}, {artist}, {album}, {show}, {kind}, {progress},
+        /// {duration}, {state}, {shuffle}, {repeat}, {device}, {volume} and {liked}
+        #[arg(long, default_value = status::DEFAULT_FORMAT)]
+        format: String,
+        /// Print the full playback state as JSON instead
+        #[arg(long, conflicts_with = "format")]
+        json: bool,
+    },
+    #[command(group(ArgGroup::new("style").required(true)))]
+    Bar {
+        /// Print waybar JSON
+        #[arg(long, group = "style")]
+        waybar: bool,
+        /// Print polybar lines with click handlers
+        #[arg(long, group = "style")]
+        polybar: bool,
+        /// Same placeholders as `status --format`
+        #[arg(long, default_value = status::DEFAULT_FORMAT)]
+        format: String,
+        /// Waybar tooltip, with the same placeholders as --format
+        #[arg(long, default_value = bar::DEFAULT_TOOLTIP_FORMAT)]
+        tooltip_format: String,
+        /// Scroll text longer than this many characters (0 never scrolls)
+        #[arg(long, default_value_t = bar::DEFAULT_WIDTH)]
+        width: usize,
+    },
+    /// Print a line of JSON for each change in playback
+    Watch,
+    /// Show a notification whenever what's playing changes
+    Notify,
+    /// Stay signed in and serve the other commands over a socket
+    Daemon,
+    /// Show up as an MPRIS player on D-Bus
+    Mpris {
+        /// Bus to connect to instead of the session bus
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Serve the HTTP control API (needs api_token in the config)
+    Api,
+    /// Show or change the config
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Let guests suggest songs from their phones
+    Party {
+        #[command(subcommand)]
+        command: PartyCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the config in effect, with overrides and defaults filled in
+    Show,
+    /// Print one setting, with dots between nested names like hooks.on_like
+    Get {
+        key: String,
+    },
+    /// Change one setting in the config file; the value is parsed as YAML
+    Set {
+        key: String,
+        value: String,
+    },
+    /// Check the config file and ROFIFY_* overrides for mistakes
+    Validate,
+    /// Open the config file in $VISUAL or $EDITOR, then check it
+    Edit,
+    /// Print where the config file is
+    Path,
+}
+
+#[derive(Subcommand)]
+enum PartyCommand {
+    /// Serve the guest page
+    Serve,
+    /// Approve or reject what guests have suggested
+    Review,
 }
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    if let Some(path) = cli.config.clone() {
+        config::set_path(path);
+    }
+
+    // nothing here needs spotify, and a broken config is what it's for
+    if let Commands::Config { command } = &cli.command {
+        return config_command(command);
+    }
+
+    // the daemon is already signed in, so it's quicker to let it do the work
+    if let Some(command) = daemon_command(&cli.command) {
+        match daemon::client::send(command).await {
+            Ok(reply) => return daemon_reply(&cli.command, reply),
+            Err(daemon::Error::NotRunning | daemon::Error::Version(..)) => (),
+            Err(error) => fail(&cli.command, error),
+        }
+    }
 
-    let program = match Config::load() {
-        Ok(config) => config.program.unwrap(),
+    let config = match Config::load() {
+        Ok(config) => config,
         Err(error) => {
-            enotify(&format!("Failed to load program from config: {error}"));
+            enotify_error("Failed to load config", &error);
             exit(1)
         },
     };
+    let program = config.program.clone().unwrap();
+    notify::set_categories(config.notification_categories.unwrap_or_default());
+    notify::set_error_template(config.notification_templates.clone().unwrap_or_default().error);
+    notify::set_sinks(
+        &config.info_sink.clone().unwrap_or_default(),
+        &config.error_sink.clone().unwrap_or_default()
+    );
+    config::warn_about_unknown_keys();
 
     let client = match auth::auth(program.clone()).await {
         Ok(client) => Arc::new(client),
         Err(error) => {
-            enotify(&format!("Failed to authenticate with spotify: {error}"));
+            enotify_error("Failed to authenticate with spotify", &error);
             exit(1);
         }
     };
@@ -42,9 +161,123 @@ async fn main() {
     match cli.command {
         Commands::Show => rofify::show(client, program).await,
         Commands::Control{ action } => if let Err(error) = controller::control(client, &action, program).await {
-            enotify(&format!("Failed to perform \"{}\": {error}", &action));
             println!("{:#?}", error);
-            exit(1)
+            fail(&cli.command, error)
+        },
+        Commands::Status { ref format, json } => match Status::fetch(&client).await {
+            Ok(status) => print_status(status, format, json),
+            Err(error) => fail(&cli.command, error),
+        },
+        Commands::Bar { waybar, polybar: _, format, tooltip_format, width } => {
+            let style = if waybar { BarStyle::Waybar } else { BarStyle::Polybar };
+            bar::run(client, BarOptions { style, format, tooltip_format, width }).await
+        },
+        Commands::Watch => watch::run(client).await,
+        Commands::Notify => now_playing::run(client, &config).await,
+        Commands::Daemon => if let Err(error) = daemon::server::run(client, program).await {
+            fail(&cli.command, error)
+        },
+        Commands::Mpris { ref address } => if let Err(error) = mpris::run(client, program, address.as_deref()).await {
+            fail(&cli.command, error)
+        },
+        Commands::Api => if let Err(error) = api::run(client, program, &config).await {
+            fail(&cli.command, error)
         },
+        Commands::Party { command: PartyCommand::Serve } => if let Err(error) = party::serve(client, program, &config).await {
+            fail(&cli.command, error)
+        },
+        Commands::Party { command: PartyCommand::Review } => if let Err(error) = party::review(&config, program).await {
+            fail(&cli.command, error)
+        },
+        // handled before signing in
+        Commands::Config { .. } => (),
+    }
+}
+
+/// What to ask the daemon for instead of running `command` here, if it's
+/// something the daemon can do.
+fn daemon_command(command: &Commands) -> Option<daemon::protocol::Command> {
+    match command {
+        Commands::Show => Some(daemon::protocol::Command::Show),
+        Commands::Control { action } => Some(daemon::protocol::Command::Control { action: *action }),
+        Commands::Status { .. } => Some(daemon::protocol::Command::Status),
+        _ => None,
+    }
+}
+
+fn config_command(command: &ConfigCommand) {
+    let result = match command {
+        ConfigCommand::Show => Config::load_unchanged()
+            .and_then(|config| Ok(serde_yaml::to_string(&config)?))
+            .map(|yaml| print!("{yaml}")),
+        ConfigCommand::Get { key } => Config::get(key).map(|value| match value {
+            Value::String(text) => println!("{text}"),
+            Value::Null => (),
+            value => print!("{}", serde_yaml::to_string(&value).unwrap_or_default()),
+        }),
+        ConfigCommand::Set { key, value } => Config::set(key, value),
+        ConfigCommand::Validate => Config::validate().map(|_| println!("{} is valid", config::config_path().display())),
+        ConfigCommand::Edit => edit_config().and_then(|_| Config::validate()),
+        ConfigCommand::Path => {
+            println!("{}", config::config_path().display());
+            Ok(())
+        },
+    };
+
+    if let Err(error) = result {
+        eprintln!("{}", error_chain(&error));
+        exit(1)
+    }
+}
+
+fn edit_config() -> config::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| DEFAULT_EDITOR.to_string());
+    let path = config::config_path();
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // the editor is run through sh, since $EDITOR often has arguments
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(&path)
+        .status()?;
+
+    if !status.success() {
+        return Err(io::Error::other(format!("{editor} exited with {status}")).into());
+    }
+    Ok(())
+}
+
+fn daemon_reply(command: &Commands, reply: Reply) {
+    if let (Commands::Status { format, json }, Reply::Status { status }) = (command, reply) {
+        print_status(status.map(|status| *status), format, *json);
+    }
+}
+
+fn print_status(status: Option<Status>, format: &str, json: bool) {
+    if json {
+        println!("{}", serde_json::to_string(&status).unwrap());
+    } else {
+        println!("{}", status.map(|status| status.format(format)).unwrap_or_default());
+    }
+}
+
+fn fail(command: &Commands, error: impl Error) -> ! {
+    match command {
+        Commands::Control { action } => enotify_error(&format!("Failed to perform \"{action}\""), &error),
+        // status bars poll this, so a notification per failure would pile up
+        Commands::Status { .. } => eprintln!("Failed to get playback status: {}", error_chain(&error)),
+        Commands::Daemon => enotify_error("Failed to run daemon", &error),
+        Commands::Mpris { .. } => enotify_error("Failed to run MPRIS bridge", &error),
+        Commands::Api => enotify_error("Failed to run HTTP API", &error),
+        Commands::Party { command: PartyCommand::Serve } => enotify_error("Failed to run party", &error),
+        Commands::Party { command: PartyCommand::Review } => enotify_error("Failed to review suggestions", &error),
+        _ => enotify(&error_chain(&error)),
     }
+    exit(1)
 }
@@ -0,0 +1,210 @@
+//! Status bar modules that keep printing the playback state as it changes.
+//!
+//! Polybar output carries its own click handlers. Waybar can't take them from
+//! the module's output, so point its `on-click` settings at `rofify control`
+//! instead, e.g.:
+//!
+//! ```json
+//! "custom/rofify": {
+//!     "exec": "rofify bar --waybar",
+//!     "return-type": "json",
+//!     "on-click": "rofify control play-pause",
+//!     "on-click-middle": "rofify control like",
+//!     "on-click-right": "rofify control next",
+//!     "on-scroll-up": "rofify control next",
+//!     "on-scroll-down": "rofify control previous"
+//! }
+//! ```
+
+use std::env;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rofify::template::escape_markup;
+use rspotify::AuthCodePkceSpotify;
+use serde::Serialize;
+
+use crate::Action;
+use crate::poll::Poller;
+use crate::status::Status;
+
+pub const DEFAULT_TOOLTIP_FORMAT: &str = "{title}\n{artist}\n{album}{show}\n{device} ({volume}%)";
+pub const DEFAULT_WIDTH: usize = 40;
+
+/// How often the marquee moves along by a character.
+const MARQUEE_STEP: Duration = Duration::from_millis(300);
+/// What goes between the end of the text and its start coming round again.
+const MARQUEE_GAP: &str = "   ";
+
+/// Mouse buttons and the actions they trigger, numbered the way polybar
+/// numbers them (4 and 5 being scroll up and down).
+const CLICK_ACTIONS: [(u8, Action); 5] = [
+    (1, Action::PlayPause),
+    (2, Action::Like),
+    (3, Action::Next),
+    (4, Action::Next),
+    (5, Action::Previous),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarStyle {
+    Waybar,
+    Polybar,
+}
+
+pub struct BarOptions {
+    pub style: BarStyle,
+    pub format: String,
+    pub tooltip_format: String,
+    /// How many characters to show before scrolling; 0 never scrolls.
+    pub width: usize,
+}
+
+/// What waybar expects on each line of a custom module with
+/// `"return-type": "json"`.
+#[derive(Serialize)]
+struct WaybarOutput {
+    text: String,
+    tooltip: String,
+    class: Vec<&'static str>,
+}
+
+/// Prints a line for the bar every time what it shows changes, until the
+/// process is killed.
+pub async fn run(client: Arc<AuthCodePkceSpotify>, options: BarOptions) {
+    let mut poller = Poller::new(client);
+    let mut status = None;
+    let mut marquee = Marquee::new(options.width);
+    let mut next_poll = Instant::now();
+    let mut last_line = None;
+
+    loop {
+        if Instant::now() >= next_poll {
+            match poller.poll().await {
+                Ok(new_status) => status = new_status,
+                Err(error) => {
+                    eprintln!("Failed to get playback status: {error}");
+                    status = None;
+                }
+            }
+            next_poll = Instant::now() + poller.delay();
+        }
+
+        let text = status
+            .as_ref()
+            .map(|status| status.format(&options.format))
+            .unwrap_or_default();
+        let item_id = status
+            .as_ref()
+            .and_then(|status| status.item.as_ref())
+            .and_then(|item| item.id.clone());
+        let playing = status.as_ref().is_some_and(|status| status.is_playing);
+        let text = marquee.frame(&text, item_id, playing);
+
+        let line = match options.style {
+            BarStyle::Waybar => waybar_line(status.as_ref(), text, &options.tooltip_format),
+            BarStyle::Polybar => polybar_line(text),
+        };
+        if last_line.as_ref() != Some(&line) {
+            println!("{line}");
+            last_line = Some(line);
+        }
+
+        let until_poll = next_poll.saturating_duration_since(Instant::now());
+        let sleep = if marquee.scrolling { until_poll.min(MARQUEE_STEP) } else { until_poll };
+        tokio::time::sleep(sleep).await;
+    }
+}
+
+fn waybar_line(status: Option<&Status>, text: String, tooltip_format: &str) -> String {
+    let mut class = vec![match status {
+        Some(status) if status.is_playing => "playing",
+        Some(_) => "paused",
+        None => "stopped",
+    }];
+    if status.and_then(|status| status.item.as_ref()).and_then(|item| item.liked) == Some(true) {
+        class.push("liked");
+    }
+
+    // waybar renders both as pango markup
+    let output = WaybarOutput {
+        text: escape_markup(&text),
+        tooltip: status
+            .map(|status| escape_markup(&status.format(tooltip_format)))
+            .unwrap_or_default(),
+        class,
+    };
+
+    serde_json::to_string(&output).unwrap()
+}
+
+fn polybar_line(text: String) -> String {
+    if text.is_empty() {
+        return text;
+    }
+
+    let exe = env::current_exe()
+        .ok()
+        .and_then(|path| path.into_os_string().into_string().ok())
+        .unwrap_or_else(|| "rofify".to_string())
+        // colons end polybar's action tags
+        .replace(':', "\\:");
+
+    // polybar draws a literal `%` as `%%`
+    let mut line = text.replace('%', "%%");
+    for (button, action) in CLICK_ACTIONS {
+        line = format!("%{{A{button}:{exe} control {action}:}}{line}%{{A}}");
+    }
+    line
+}
+
+/// Scrolls text that's too long for the bar, one character per
+/// [`MARQUEE_STEP`].
+struct Marquee {
+    width: usize,
+    item_id: Option<String>,
+    offset: usize,
+    moved_at: Instant,
+    scrolling: bool,
+}
+
+impl Marquee {
+    fn new(width: usize) -> Self {
+        Self {
+            width,
+            item_id: None,
+            offset: 0,
+            moved_at: Instant::now(),
+            scrolling: false,
+        }
+    }
+
+    /// The part of `text` to show right now. It only moves while playing, and
+    /// starts over from the beginning whenever the item changes.
+    fn frame(&mut self, text: &str, item_id: Option<String>, playing: bool) -> String {
+        if item_id != self.item_id {
+            self.item_id = item_id;
+            self.offset = 0;
+            self.moved_at = Instant::now();
+        }
+
+        let length = text.chars().count();
+        self.scrolling = playing && self.width > 0 && length > self.width;
+
+        if self.width == 0 || length <= self.width {
+            return text.to_string();
+        }
+
+        if self.scrolling && self.moved_at.elapsed() >= MARQUEE_STEP {
+            self.offset = (self.offset + 1) % (length + MARQUEE_GAP.chars().count());
+            self.moved_at = Instant::now();
+        }
+
+        text.chars()
+            .chain(MARQUEE_GAP.chars())
+            .cycle()
+            .skip(self.offset)
+            .take(self.width)
+            .collect()
+    }
+}
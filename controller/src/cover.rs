@@ -0,0 +1,123 @@
+//! Downloading and caching cover art, shared by notifications and exports.
+
+use std::fs::{self, File};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use image::{DynamicImage, ImageOutputFormat};
+use image::imageops::FilterType;
+use notify::dirs::{self, write_atomically};
+use rofify::config::{Config, DEFAULT_COVER_CACHE_MAX_MB, DEFAULT_COVER_SIZE};
+
+use crate::Result;
+use crate::status::Item;
+
+const COVERS_DIR: &str = "covers";
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// A cover, and where it's cached for anything that needs a file.
+pub struct Cover {
+    pub image: DynamicImage,
+    pub path: PathBuf,
+}
+
+/// Covers scaled to the configured size, one file per album or show, with
+/// the least recently used evicted once the cache outgrows its budget.
+pub struct CoverCache {
+    dir: PathBuf,
+    size: u32,
+    max_bytes: u64,
+}
+
+impl CoverCache {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            dir: dirs::cache_dir().join(COVERS_DIR),
+            size: config.cover_size.unwrap_or(DEFAULT_COVER_SIZE),
+            max_bytes: config.cover_cache_max_mb.unwrap_or(DEFAULT_COVER_CACHE_MAX_MB) * BYTES_PER_MB,
+        }
+    }
+
+    /// Gets `item`'s cover from the cache, downloading it on a miss. `None`
+    /// if it has no cover.
+    pub async fn get(&self, item: &Item) -> Result<Option<Cover>> {
+        let (url, path) = match (&item.cover_url, self.path(item)) {
+            (Some(url), Some(path)) => (url, path),
+            _ => return Ok(None),
+        };
+
+        if let Ok(image) = image::open(&path) {
+            // the modified time doubles as the last use for eviction
+            File::options().append(true).open(&path)?.set_modified(SystemTime::now())?;
+            return Ok(Some(Cover { image, path }));
+        }
+
+        let mut image = download(url).await?;
+        if image.width() > self.size || image.height() > self.size {
+            image = image.resize(self.size, self.size, FilterType::Lanczos3);
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        save(&image, &path)?;
+        self.evict(&path)?;
+        Ok(Some(Cover { image, path }))
+    }
+
+    /// Where `item`'s cover is cached, if it's been cached already.
+    pub fn cached_path(&self, item: &Item) -> Option<PathBuf> {
+        self.path(item).filter(|path| path.exists())
+    }
+
+    fn path(&self, item: &Item) -> Option<PathBuf> {
+        let url = item.cover_url.as_ref()?;
+        // the url's last segment identifies the image when nothing else does
+        let key = item.cover_id.as_deref().or_else(|| url.rsplit('/').next())?;
+
+        Some(self.dir.join(format!("{key}-{}.png", self.size)))
+    }
+
+    /// Removes the least recently used covers, other than `keep`, until the
+    /// cache fits its budget.
+    fn evict(&self, keep: &Path) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, len, _)| len).sum();
+        entries.sort();
+
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+pub async fn download(url: &str) -> Result<DynamicImage> {
+    let raw_image = reqwest::get(url)
+        .await?
+        .bytes()
+        .await?;
+
+    Ok(image::load_from_memory(&raw_image)?)
+}
+
+/// Saves `image` as a PNG without anyone reading `path` ever seeing half of
+/// it.
+pub fn save(image: &DynamicImage, path: &Path) -> Result<()> {
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageOutputFormat::Png)?;
+    write_atomically(path, png.get_ref())?;
+    Ok(())
+}
@@ -0,0 +1,35 @@
+//! Writes what's playing into files that streaming software like OBS can
+//! show as text and image sources.
+
+use std::fs;
+use std::path::Path;
+
+use image::DynamicImage;
+use notify::dirs::write_atomically;
+
+use crate::Result;
+use crate::cover;
+use crate::status::Item;
+
+pub const TEXT_FILE: &str = "now_playing.txt";
+pub const JSON_FILE: &str = "now_playing.json";
+pub const COVER_FILE: &str = "cover.png";
+
+/// Writes `item` to the text and JSON files in `dir`, and its cover to
+/// `cover.png`, removing a stale cover if it has none.
+pub fn export(dir: &Path, item: &Item, cover: Option<&DynamicImage>) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    let text = format!("{} - {}", item.artists.join(", "), item.title);
+    write_atomically(&dir.join(TEXT_FILE), text.as_bytes())?;
+    write_atomically(&dir.join(JSON_FILE), &serde_json::to_vec_pretty(item)?)?;
+
+    let cover_path = dir.join(COVER_FILE);
+    match cover {
+        Some(cover) => cover::save(cover, &cover_path)?,
+        None => if cover_path.exists() {
+            fs::remove_file(cover_path)?;
+        },
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! Runs the shell commands configured in [`Hooks`] on playback events.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use rofify::config::Hooks;
+use rofify::template::format_duration;
+
+use crate::status::{ItemKind, Status};
+use crate::watch::Event;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    TrackChange,
+    Pause,
+    Resume,
+    DeviceChange,
+    Like,
+    Error,
+}
+
+impl Hook {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::TrackChange => "track-change",
+            Self::Pause => "pause",
+            Self::Resume => "resume",
+            Self::DeviceChange => "device-change",
+            Self::Like => "like",
+            Self::Error => "error",
+        }
+    }
+
+    fn command<'a>(&self, hooks: &'a Hooks) -> Option<&'a str> {
+        let command = match self {
+            Self::TrackChange => &hooks.on_track_change,
+            Self::Pause => &hooks.on_pause,
+            Self::Resume => &hooks.on_resume,
+            Self::DeviceChange => &hooks.on_device_change,
+            Self::Like => &hooks.on_like,
+            Self::Error => &hooks.on_error,
+        };
+        command.as_deref()
+    }
+
+    /// The hook a change in playback fires, if any.
+    pub fn for_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::TrackChanged { item: Some(_) } => Some(Self::TrackChange),
+            Event::Paused => Some(Self::Pause),
+            Event::Resumed => Some(Self::Resume),
+            Event::DeviceChanged { .. } => Some(Self::DeviceChange),
+            _ => None,
+        }
+    }
+}
+
+/// What a hook gets to know about the event that fired it.
+#[derive(Default)]
+pub struct Context<'a> {
+    pub status: Option<&'a Status>,
+    pub cover_path: Option<&'a Path>,
+    pub error: Option<&'a str>,
+}
+
+/// Runs `hook`'s command, if one is configured, without waiting for it to
+/// finish.
+pub fn fire(hooks: &Hooks, hook: Hook, context: Context) {
+    let command = match hook.command(hooks) {
+        Some(command) => command,
+        None => return,
+    };
+
+    let spawned = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(environment(hook, &context))
+        .stdin(Stdio::null())
+        .spawn();
+
+    match spawned {
+        // reaped in the background so slow hooks don't hold up playback
+        Ok(mut child) => {
+            thread::spawn(move || child.wait());
+        },
+        Err(error) => eprintln!("Failed to run {} hook: {error}", hook.name()),
+    }
+}
+
+fn environment(hook: Hook, context: &Context) -> Vec<(&'static str, String)> {
+    let mut variables = vec![("ROFIFY_EVENT", hook.name().to_string())];
+
+    if let Some(status) = context.status {
+        variables.extend([
+            ("ROFIFY_IS_PLAYING", status.is_playing.to_string()),
+            ("ROFIFY_PROGRESS", format_duration(status.progress_ms)),
+            ("ROFIFY_PROGRESS_MS", status.progress_ms.to_string()),
+            ("ROFIFY_SHUFFLE", status.shuffle.to_string()),
+            // not ROFIFY_DEVICE_ID, which would override the device_id of
+            // any rofify the hook runs
+            ("ROFIFY_CURRENT_DEVICE", status.device.name.clone()),
+            ("ROFIFY_CURRENT_DEVICE_ID", status.device.id.clone().unwrap_or_default()),
+            ("ROFIFY_VOLUME", status.volume.map(|volume| volume.to_string()).unwrap_or_default()),
+        ]);
+    }
+
+    if let Some(item) = context.status.and_then(|status| status.item.as_ref()) {
+        let kind = match item.kind {
+            ItemKind::Track => "track",
+            ItemKind::Episode => "episode",
+        };
+
+        variables.extend([
+            ("ROFIFY_KIND", kind.to_string()),
+            ("ROFIFY_ID", item.id.clone().unwrap_or_default()),
+            ("ROFIFY_URI", item.uri.clone().unwrap_or_default()),
+            ("ROFIFY_TITLE", item.title.clone()),
+            ("ROFIFY_ARTIST", item.artists.join(", ")),
+            ("ROFIFY_ALBUM", item.album.clone().unwrap_or_default()),
+            ("ROFIFY_SHOW", item.show.clone().unwrap_or_default()),
+            ("ROFIFY_YEAR", item.year.map(|year| year.to_string()).unwrap_or_default()),
+            ("ROFIFY_DURATION", format_duration(item.duration_ms)),
+            ("ROFIFY_DURATION_MS", item.duration_ms.to_string()),
+            ("ROFIFY_COVER_URL", item.cover_url.clone().unwrap_or_default()),
+            ("ROFIFY_LIKED", item.liked.map(|liked| liked.to_string()).unwrap_or_default()),
+        ]);
+    }
+
+    if let Some(cover_path) = context.cover_path {
+        variables.push(("ROFIFY_COVER_PATH", cover_path.to_string_lossy().into_owned()));
+    }
+
+    if let Some(error) = context.error {
+        variables.push(("ROFIFY_ERROR", error.to_string()));
+    }
+
+    variables
+}
@@ -1,13 +1,36 @@
-use notify::{notify, cover_art_icon_path, icons_dir};
+pub mod bar;
+pub mod cover;
+pub mod export;
+pub mod hooks;
+pub mod now_playing;
+pub mod poll;
+pub mod status;
+pub mod watch;
+
+use hooks::Hook;
+use notify::{Category, NotificationTemplate, notify, notify_in};
+use rofify::config::{Config, Hooks, NotificationTemplates};
 use rofify::menu::MenuProgram;
+use rofify::template::render_notification;
 use rofify::menu::device::device_id;
-use rspotify::model::{AdditionalType, PlayableItem, CurrentPlaybackContext, RepeatState};
+use reqwest::StatusCode;
+use rspotify::http::HttpError;
+use rspotify::model::{IdError, TrackId, EpisodeId, PlayableId, AdditionalType, PlayableItem, CurrentPlaybackContext, RepeatState, Device, DeviceType};
 use rspotify::{AuthCodePkceSpotify, ClientError};
 use rspotify::prelude::OAuthClient;
 use std::future::Future;
-use std::{result, fmt, fs, io};
+use std::time::{Duration, Instant};
+use std::{result, fmt, io};
 use std::sync::Arc;
 use clap::Subcommand;
+use serde::{Serialize, Deserialize};
+
+use status::Status;
+
+/// How often to check whether spotify has registered a playback transfer.
+const TRANSFER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// How long to wait for a transfer to register before retrying anyway.
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(5);
 
 
 #[derive(thiserror::Error, Debug)]
@@ -18,19 +41,42 @@ pub enum Error {
     Client(#[from] ClientError),
     #[error("Http error: {0}")]
     Http(#[from] reqwest::Error),
+    #[error("Invalid spotify id: {0}")]
+    Id(#[from] IdError),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
     #[error("Image error: {0}")]
     Image(#[from] image::ImageError),
-    #[error("Failed to convert os path to string.")]
-    PathToString,
     #[error("Nothing is playing right now.")]
     NoContext,
     #[error("Item is not a playable track.")]
     NotTrack,
+    #[error("No devices are available to play on.")]
+    NoDevice,
+    #[error("Failed to load config: {0}")]
+    Config(#[from] rofify::config::Error),
+}
+
+impl Error {
+    /// Whether the error means there's no active device to act on, either
+    /// because nothing is playing or because spotify rejected the request
+    /// with NO_ACTIVE_DEVICE (which it reports as a 404).
+    fn is_inactive_device(&self) -> bool {
+        match self {
+            Self::NoContext => true,
+            Self::Client(ClientError::Http(error)) => matches!(
+                error.as_ref(),
+                HttpError::StatusCode(response) if response.status() == StatusCode::NOT_FOUND
+            ),
+            _ => false,
+        }
+    }
 }
 
 pub type Result<T> = result::Result<T, Error>;
 
-#[derive(Subcommand)]
+#[derive(Subcommand, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
 pub enum Action {
     PlayPause,
     Next,
@@ -56,19 +102,39 @@ impl fmt::Display for Action {
     }
 }
 
-struct Controller {
+impl Action {
+    /// Whether the action controls playback, and so is worth activating a
+    /// device for when none is active. Liking only touches the library, so
+    /// it never moves playback.
+    fn needs_device(&self) -> bool {
+        !matches!(self, Self::Like | Self::OnChange)
+    }
+}
+
+/// Performs actions on the device and with the config it was created with.
+/// Long-running processes keep one around, so that actions don't look up the
+/// device and config again, or ask for a device, every time.
+pub struct Controller {
     client: Arc<AuthCodePkceSpotify>,
     device_id: Option<String>,
+    config: Config,
 }
 
 impl Controller {
-    async fn new(client: Arc<AuthCodePkceSpotify>, program: MenuProgram) -> Self {
+    pub async fn new(client: Arc<AuthCodePkceSpotify>, program: MenuProgram) -> Result<Self> {
+        let config = Config::load()?;
         let device_id = device_id(
             Arc::clone(&client),
             program.clone()
         ).await;
 
-        Self { client, device_id }
+        Ok(Self::with_device(client, device_id, config))
+    }
+
+    /// A controller for `device_id` without looking anything up, so it never
+    /// asks for a device or goes to the network.
+    pub fn with_device(client: Arc<AuthCodePkceSpotify>, device_id: Option<String>, config: Config) -> Self {
+        Self { client, device_id, config }
     }
 
     async fn control<F, Fut>(&self, with_context: F) -> Result<()>
@@ -84,7 +150,7 @@ impl Controller {
             ])
         ).await?;
 
-        Ok(match maybe_current_playback_context {
+        match maybe_current_playback_context {
             Some(current_playback_context) => {
                 with_context(
                     Arc::clone(&self.client),
@@ -94,49 +160,208 @@ impl Controller {
                 Ok(())
             },
             None => Err(Error::NoContext)
-        }?)
+        }
     }
 
-    async fn next(&self) -> Result<()> {
+    pub async fn next(&self) -> Result<()> {
         self.client.next_track(self.device_id.as_deref()).await?;
         Ok(())
     }
 
-    async fn previous(&self) -> Result<()> {
+    pub async fn previous(&self) -> Result<()> {
         self.client.previous_track(self.device_id.as_deref()).await?;
         Ok(())
     }
 
-    async fn play_pause(&self) -> Result<()> {
+    pub async fn play_pause(&self) -> Result<()> {
         self.control(|client, context, device_id| async move {
             play_pause(client, context, device_id).await
         }).await
     }
 
-    async fn shuffle(&self) -> Result<()> {
+    pub async fn shuffle(&self) -> Result<()> {
+        let template = self.templates().shuffle;
         self.control(|client, context, device_id| async move {
-            shuffle(client, context, device_id).await
+            shuffle(client, context, device_id, &template).await
         }).await
     }
 
-    async fn repeat(&self) -> Result<()> {
+    pub async fn repeat(&self) -> Result<()> {
+        let template = self.templates().repeat;
         self.control(|client, context, device_id| async move {
-            repeat(client, context, device_id).await
+            repeat(client, context, device_id, &template).await
         }).await
     }
 
+    pub async fn play(&self) -> Result<()> {
+        self.client.resume_playback(self.device_id.as_deref(), None).await?;
+        Ok(())
+    }
+
+    pub async fn pause(&self) -> Result<()> {
+        self.client.pause_playback(self.device_id.as_deref()).await?;
+        Ok(())
+    }
+
+    pub async fn seek(&self, position_ms: u64) -> Result<()> {
+        let position = chrono::Duration::milliseconds(position_ms as i64);
+        self.client.seek_track(position, self.device_id.as_deref()).await?;
+        Ok(())
+    }
+
+    /// Sets the volume, clamped to 0-100.
+    pub async fn set_volume(&self, volume_percent: u8) -> Result<()> {
+        let volume_percent = volume_percent.min(100);
+        self.client.volume(volume_percent, self.device_id.as_deref()).await?;
+        notify_template(Some(Category::Volume), &self.templates().volume, |key| {
+            (key == "volume").then(|| volume_percent.to_string())
+        });
+        Ok(())
+    }
+
+    pub async fn set_shuffle(&self, shuffle: bool) -> Result<()> {
+        self.client.shuffle(shuffle, self.device_id.as_deref()).await?;
+        Ok(())
+    }
+
+    pub async fn set_repeat(&self, repeat_state: RepeatState) -> Result<()> {
+        self.client.repeat(repeat_state, self.device_id.as_deref()).await?;
+        Ok(())
+    }
+
+    /// Plays a spotify track or episode URI.
+    pub async fn play_uri(&self, uri: &str) -> Result<()> {
+        self.client.start_uris_playback(
+            [playable_id(uri)?],
+            self.device_id.as_deref(),
+            None,
+            None
+        ).await?;
+        Ok(())
+    }
 
-    async fn like(&self) -> Result<()> {
+    /// Adds a spotify track or episode URI to the queue.
+    pub async fn queue_uri(&self, uri: &str) -> Result<()> {
+        self.client.add_item_to_queue(playable_id(uri)?, self.device_id.as_deref()).await?;
+        Ok(())
+    }
+
+
+    pub async fn like(&self) -> Result<()> {
+        let templates = self.templates();
+        let hooks = self.config.hooks.clone().unwrap_or_default();
         self.control(|client, context, _| async move {
-            like(client, context).await
+            like(client, context, &templates, &hooks).await
         }).await
     }
 
-    async fn on_change(&self) -> Result<()> {
+    pub async fn on_change(&self) -> Result<()> {
         self.control(|_, context, _| async move {
-            on_change(context).await
+            now_playing::on_change(&Status::from(context), &self.config, None).await
         }).await
     }
+
+    fn templates(&self) -> NotificationTemplates {
+        self.config.notification_templates.clone().unwrap_or_default()
+    }
+
+    /// Transfers playback to the best available device, starting it if
+    /// `play`, and uses it for any further actions.
+    async fn activate_device(&mut self, play: bool) -> Result<()> {
+        let devices = self.client.device().await?;
+        let id = best_device(&devices, self.device_id.as_deref())
+            .and_then(|device| device.id.clone())
+            .ok_or(Error::NoDevice)?;
+
+        self.client.transfer_playback(&id, Some(play)).await?;
+        self.wait_for_device(&id).await?;
+
+        self.device_id = Some(id);
+        Ok(())
+    }
+
+    /// Waits until spotify reports playback on device `id`, since a transfer
+    /// takes a moment to register and how long depends on the device.
+    async fn wait_for_device(&self, id: &str) -> Result<()> {
+        let deadline = Instant::now() + TRANSFER_TIMEOUT;
+
+        while Instant::now() < deadline {
+            let context = self.client.current_playback(None, None::<[&AdditionalType; 0]>).await?;
+            if context.is_some_and(|context| context.device.id.as_deref() == Some(id)) {
+                break;
+            }
+            tokio::time::sleep(TRANSFER_POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+
+    /// Gets `action` done after it failed for want of an active device.
+    async fn recover(&mut self, action: &Action) -> Result<()> {
+        if *action == Action::PlayPause {
+            // resuming is enough when the device has only gone idle
+            match self.play().await {
+                Err(error) if error.is_inactive_device() => (),
+                otherwise => return otherwise,
+            }
+            // transferring with playback on is the action itself
+            return self.activate_device(true).await;
+        }
+
+        self.activate_device(false).await?;
+        self.perform(action).await
+    }
+
+    /// Performs `action`, and if that failed for want of an active device,
+    /// activates one and tries again.
+    pub async fn act(&mut self, action: &Action) -> Result<()> {
+        let result = match self.perform(action).await {
+            Err(error) if action.needs_device() && error.is_inactive_device() => self.recover(action).await,
+            otherwise => otherwise
+        };
+
+        if let Err(error) = &result {
+            let hooks = self.config.hooks.clone().unwrap_or_default();
+            hooks::fire(&hooks, Hook::Error, hooks::Context {
+                error: Some(&error.to_string()),
+                ..Default::default()
+            });
+        }
+        result
+    }
+
+    async fn perform(&self, action: &Action) -> Result<()> {
+        match action {
+            Action::PlayPause => self.play_pause().await,
+            Action::Next => self.next().await,
+            Action::Previous => self.previous().await,
+            Action::Like => self.like().await,
+            Action::Shuffle => self.shuffle().await,
+            Action::Repeat => self.repeat().await,
+            Action::OnChange => self.on_change().await,
+        }
+    }
+}
+
+fn playable_id(uri: &str) -> Result<PlayableId<'_>> {
+    match TrackId::from_uri(uri) {
+        Ok(id) => Ok(PlayableId::Track(id)),
+        Err(_) => Ok(PlayableId::Episode(EpisodeId::from_uri(uri)?)),
+    }
+}
+
+/// Picks the device to transfer playback to: the preferred one if it's
+/// available, otherwise whichever is already active, otherwise the first
+/// unrestricted computer, otherwise any unrestricted device.
+fn best_device<'a>(devices: &'a [Device], preferred: Option<&str>) -> Option<&'a Device> {
+    let usable = || devices
+        .iter()
+        .filter(|device| device.id.is_some() && !device.is_restricted);
+
+    usable()
+        .find(|device| device.id.as_deref() == preferred)
+        .or_else(|| usable().find(|device| device.is_active))
+        .or_else(|| usable().find(|device| device._type == DeviceType::Computer))
+        .or_else(|| usable().next())
 }
 
 async fn play_pause(
@@ -156,18 +381,26 @@ async fn play_pause(
 async fn shuffle(
     client: Arc<AuthCodePkceSpotify>,
     context: CurrentPlaybackContext,
-    device_id: Option<String>
+    device_id: Option<String>,
+    template: &NotificationTemplate
 ) -> Result<()> {
     let is_shuffled = context.shuffle_state;
     client.shuffle(!is_shuffled, device_id.as_deref()).await?;
-    notify("Shuffle", if is_shuffled { "disabled" } else { "enabled" }, None);
+
+    let status = Status::from(context);
+    let state = if is_shuffled { "disabled" } else { "enabled" };
+    notify_template(Some(Category::ShuffleRepeat), template, |key| match key {
+        "state" => Some(state.to_string()),
+        key => status.placeholder(key),
+    });
     Ok(())
 }
 
 async fn repeat(
     client: Arc<AuthCodePkceSpotify>,
     context: CurrentPlaybackContext,
-    device_id: Option<String>
+    device_id: Option<String>,
+    template: &NotificationTemplate
 ) -> Result<()> {
     let repeat_state = context.repeat_state;
     let (new_repeat_state, name) = match repeat_state {
@@ -175,102 +408,64 @@ async fn repeat(
         RepeatState::Context => (RepeatState::Track, "track"),
         RepeatState::Track => (RepeatState::Off, "off"),
     };
-    client.repeat(new_repeat_state.clone(), device_id.as_deref()).await?;
-    notify("Repeat", name, None);
+    client.repeat(new_repeat_state, device_id.as_deref()).await?;
+
+    let status = Status::from(context);
+    notify_template(Some(Category::ShuffleRepeat), template, |key| match key {
+        "state" => Some(name.to_string()),
+        key => status.placeholder(key),
+    });
     Ok(())
 }
 
+/// Likes the current track, firing the like hook only if it wasn't liked
+/// already.
 async fn like(
     client: Arc<AuthCodePkceSpotify>,
     context: CurrentPlaybackContext,
+    templates: &NotificationTemplates,
+    hooks: &Hooks
 ) -> Result<()> {
-    match context.item {
-        Some(PlayableItem::Track(track)) => {
-            let artist_names: Vec<&str> = track.artists
-                .iter()
-                .map(|artist| artist.name.as_str())
-                .collect();
-            let formatted_track = format!("{} | {} | {}", track.name, track.album.name, artist_names.join(", "));
-
-            if !client.current_user_saved_tracks_contains([track.id.clone().unwrap()]).await?[0] {
-                client.current_user_saved_tracks_add([track.id.clone().unwrap()]).await?;
-
-                notify("Added to liked songs:", &formatted_track, None);
-                Ok(())
-            } else {
-                notify("Already in liked songs:", &formatted_track, None);
-                Ok(())
-            }
-        },
-        _ => Err(Error::NotTrack)
+    let track_id = match &context.item {
+        Some(PlayableItem::Track(track)) => track.id.clone().ok_or(Error::NotTrack)?,
+        _ => return Err(Error::NotTrack),
+    };
+    let mut status = Status::from(context);
+    let already_liked = client.current_user_saved_tracks_contains([track_id.clone()]).await?[0];
+
+    if let Some(item) = status.item.as_mut() {
+        item.liked = Some(true);
     }
+    let template = if !already_liked {
+        client.current_user_saved_tracks_add([track_id]).await?;
+        hooks::fire(hooks, Hook::Like, hooks::Context {
+            status: Some(&status),
+            ..Default::default()
+        });
+        &templates.like
+    } else {
+        &templates.already_liked
+    };
 
+    notify_template(None, template, |key| status.placeholder(key));
+    Ok(())
 }
 
-async fn on_change(
-    context: CurrentPlaybackContext,
-) -> Result<()> {
-    match context.item {
-        Some(PlayableItem::Track(track)) => {
-            let artist_names: Vec<&str> = track.artists
-                .iter()
-                .map(|artist| artist.name.as_str())
-                .collect();
-
-            let cover_art = track.album.images.last();
-
-            let raw_image = reqwest::get(&cover_art.unwrap().url)
-                .await?
-                .bytes()
-            .await?;
-            let cover_art_icon = image::load_from_memory(&raw_image)?;
-            fs::create_dir_all(icons_dir())?;
-            cover_art_icon.save(cover_art_icon_path())?;
-
-            match cover_art_icon_path().into_os_string().into_string() {
-                Ok(icon_path) => Ok(notify(
-                    &track.name,
-                    &format!("{} - {}", artist_names.join(", "), track.album.name),
-                    Some(icon_path)
-                )),
-                Err(_) => Err(Error::PathToString)
-            }
-        },
-        _ => Err(Error::NotTrack)
+/// Sends the notification `template` describes, unless it's turned off, in
+/// `category` if it has one.
+pub(crate) fn notify_template<F>(category: Option<Category>, template: &NotificationTemplate, value: F)
+where
+    F: Fn(&str) -> Option<String>
+{
+    if let Some((summary, body)) = render_notification(template, value) {
+        match category {
+            Some(category) => notify_in(category, &summary, &body, None),
+            None => notify(&summary, &body, None),
+        }
     }
 }
 
 pub async fn control(client: Arc<AuthCodePkceSpotify>, action: &Action, program: MenuProgram) -> Result<()> {
-    let controller = Controller::new(Arc::clone(&client), program).await;
-    match action {
-        Action::PlayPause => {
-            match controller.play_pause().await {
-                Err(Error::NoContext) => controller
-                    .client
-                    .resume_playback(controller.device_id.as_deref(), None)
-                    .await?,
-                otherwise => otherwise?
-            };
-        },
-        Action::Next => {
-            controller.next().await?;
-        },
-        Action::Previous => {
-            controller.previous().await?;
-        },
-        Action::Like => {
-            controller.like().await?;
-        },
-        Action::Shuffle => {
-            controller.shuffle().await?;
-        },
-        Action::Repeat => {
-            controller.repeat().await?;
-        },
-        Action::OnChange => {
-            controller.on_change().await?;
-        }
-    };
-
-    Ok(())
+    let mut controller = Controller::new(Arc::clone(&client), program).await?;
+    controller.act(action).await
 }
@@ -0,0 +1,201 @@
+use std::{fs, io};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use notify::{Button, Category, Icon, NotificationTemplate, notify_in, notify_with_buttons, enotify_error};
+use notify::dirs;
+use rofify::config::{Config, state_path};
+use rofify::template::render_notification;
+use rspotify::AuthCodePkceSpotify;
+use tokio::sync::Mutex;
+
+use crate::{Action, Controller, Error, Result, export, notify_template};
+use crate::cover::{Cover, CoverCache};
+use crate::hooks::{self, Hook};
+use crate::status::Status;
+use crate::watch::{Event, Watcher};
+
+/// Remembers the last item notified about, so that restarts and repeated
+/// `on-change` calls don't notify about the same thing twice.
+const LAST_ITEM_FILE: &str = "last_item";
+
+/// Handles a click on one of a notification's buttons.
+pub type OnClick = Box<dyn FnOnce(Action) + Send>;
+
+/// Notifies about what's playing and exports it if the config asks for it,
+/// unless it was the last item notified about. The notification gets
+/// buttons if there's an `on_click` to handle them.
+pub async fn on_change(status: &Status, config: &Config, on_click: Option<OnClick>) -> Result<()> {
+    let item = status.item.as_ref().ok_or(Error::NoContext)?;
+    if item.id.is_some() && item.id == last_item_id() {
+        return Ok(());
+    }
+
+    let cover = CoverCache::new(config).get(item).await?;
+    let template = config.notification_templates.clone().unwrap_or_default().on_change;
+    notify_item(status, &template, cover.as_ref(), on_click);
+
+    if let Some(export_dir) = &config.export_dir {
+        export::export(export_dir, item, cover.as_ref().map(|cover| &cover.image))?;
+    }
+
+    if let Some(id) = &item.id {
+        fs::create_dir_all(dirs::state_dir())?;
+        fs::write(last_item_path(), id)?;
+    }
+    Ok(())
+}
+
+/// Shows a notification for every new item, and for pausing and resuming if
+/// the config asks for it, and runs the configured hooks, until the process
+/// is killed.
+pub async fn run(client: Arc<AuthCodePkceSpotify>, config: &Config) {
+    let notify_on_pause_resume = config.notify_on_pause_resume.unwrap_or_default();
+    let hook_commands = config.hooks.clone().unwrap_or_default();
+    let covers = CoverCache::new(config);
+    let program = config.program.clone().unwrap();
+    // set up once, so clicks don't each look up the device and config
+    let controller = match config.notification_buttons.unwrap_or_default() {
+        true => match Controller::new(Arc::clone(&client), program).await {
+            Ok(controller) => Some(Arc::new(Mutex::new(controller))),
+            Err(error) => {
+                enotify_error("Failed to set up notification buttons", &error);
+                None
+            },
+        },
+        false => None,
+    };
+    let templates = config.notification_templates.clone().unwrap_or_default();
+    // replaced notifications keep their id, so clicks reach the waits of
+    // every notification it has replaced as well
+    let latest_notification = Arc::new(AtomicU64::new(0));
+    let mut watcher = Watcher::new(Arc::clone(&client));
+    let mut first_poll = true;
+
+    loop {
+        let (events, status) = match watcher.next().await {
+            Ok(changes) => changes,
+            Err(error) => {
+                eprintln!("Failed to get playback status: {error}");
+                continue;
+            }
+        };
+        for event in events {
+            // everything looks like it just resumed on the first poll
+            let hook = Hook::for_event(&event).filter(|hook| !first_poll || *hook == Hook::TrackChange);
+            let mut cover_path = None;
+
+            match (event, &status) {
+                (Event::TrackChanged { item: Some(item) }, Some(status)) => {
+                    let on_click = controller.as_ref().map(|controller| {
+                        perform_on_click(Arc::clone(controller), &latest_notification)
+                    });
+                    match on_change(status, config, on_click).await {
+                        Ok(_) => cover_path = covers.cached_path(&item),
+                        Err(error) => enotify_error("Failed to show what's playing", &error),
+                    }
+                },
+                (Event::Paused, Some(status)) if notify_on_pause_resume && !first_poll => {
+                    notify_template(Some(Category::NowPlaying), &templates.pause, |key| status.placeholder(key))
+                },
+                (Event::Resumed, Some(status)) if notify_on_pause_resume && !first_poll => {
+                    notify_template(Some(Category::NowPlaying), &templates.resume, |key| status.placeholder(key))
+                },
+                _ => (),
+            }
+
+            if let Some(hook) = hook {
+                hooks::fire(&hook_commands, hook, hooks::Context {
+                    status: status.as_ref(),
+                    cover_path: cover_path.as_deref(),
+                    error: None,
+                });
+            }
+        }
+
+        first_poll = false;
+    }
+}
+
+/// Performs the action of the button clicked, as long as the notification
+/// hasn't been replaced since.
+fn perform_on_click(controller: Arc<Mutex<Controller>>, latest_notification: &Arc<AtomicU64>) -> OnClick {
+    let notification = latest_notification.fetch_add(1, Ordering::SeqCst) + 1;
+    let latest_notification = Arc::clone(latest_notification);
+    let runtime = tokio::runtime::Handle::current();
+
+    Box::new(move |action| {
+        if latest_notification.load(Ordering::SeqCst) != notification {
+            return;
+        }
+        runtime.spawn(async move {
+            if let Err(error) = controller.lock().await.act(&action).await {
+                enotify_error(&format!("Failed to perform \"{action}\""), &error);
+            }
+        });
+    })
+}
+
+fn notify_item(
+    status: &Status,
+    template: &NotificationTemplate,
+    cover: Option<&Cover>,
+    on_click: Option<OnClick>
+) {
+    // falls back to the app icon
+    let icon = cover.map(|cover| Icon::Image {
+        image: cover.image.clone(),
+        fallback: Some(cover.path.clone()),
+    });
+
+    let (summary, body) = match render_notification(template, |key| status.placeholder(key)) {
+        Some(text) => text,
+        None => return,
+    };
+
+    match on_click {
+        Some(on_click) => {
+            let buttons = buttons(status);
+            notify_with_buttons(
+                Category::NowPlaying,
+                &summary,
+                &body,
+                icon,
+                &buttons.map(|(button, _)| button),
+                move |key| {
+                    if let Some((_, action)) = buttons.into_iter().find(|(button, _)| button.key == key) {
+                        on_click(action);
+                    }
+                }
+            )
+        },
+        None => notify_in(Category::NowPlaying, &summary, &body, icon),
+    }
+}
+
+/// The buttons on a now playing notification, and what they do.
+fn buttons(status: &Status) -> [(Button, Action); 3] {
+    let play_pause = if status.is_playing { "Pause" } else { "Play" };
+
+    [
+        (Button { key: "next", label: "Next" }, Action::Next),
+        (Button { key: "like", label: "Like" }, Action::Like),
+        (Button { key: "play-pause", label: play_pause }, Action::PlayPause),
+    ]
+}
+
+fn last_item_path() -> PathBuf {
+    state_path(LAST_ITEM_FILE)
+}
+
+fn last_item_id() -> Option<String> {
+    match fs::read_to_string(last_item_path()) {
+        Ok(id) => Some(id.trim().to_string()),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => {
+            eprintln!("Failed to read the last notified item: {error}");
+            None
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rspotify::AuthCodePkceSpotify;
+
+use crate::Result;
+use crate::status::Status;
+
+/// How often to poll while something is playing.
+const PLAYING_INTERVAL: Duration = Duration::from_secs(1);
+/// Where the backoff starts while paused, stopped or failing.
+const MIN_IDLE_INTERVAL: Duration = Duration::from_secs(2);
+/// Where the backoff tops out.
+const MAX_IDLE_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a track's liked state is trusted before it's looked up again.
+const LIKED_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Polls the playback state at an adaptive interval: steady while something
+/// is playing, backing off while paused, stopped or failing, and snapping
+/// back as soon as anything changes.
+pub struct Poller {
+    client: Arc<AuthCodePkceSpotify>,
+    last: Option<Status>,
+    liked_at: Option<Instant>,
+    idle_interval: Duration,
+    failing: bool,
+}
+
+impl Poller {
+    pub fn new(client: Arc<AuthCodePkceSpotify>) -> Self {
+        Self {
+            client,
+            last: None,
+            liked_at: None,
+            idle_interval: MIN_IDLE_INTERVAL,
+            failing: false,
+        }
+    }
+
+    /// Fetches the playback state. The liked state is only looked up again
+    /// when the item changes or it's gone stale, to go easy on rate limits.
+    pub async fn poll(&mut self) -> Result<Option<Status>> {
+        let maybe_status = match Status::fetch_unliked(&self.client).await {
+            Ok(maybe_status) => maybe_status,
+            Err(error) => {
+                self.failing = true;
+                self.back_off();
+                return Err(error);
+            }
+        };
+
+        let maybe_status = match maybe_status {
+            Some(mut status) => {
+                let known_liked = self.last
+                    .as_ref()
+                    .and_then(|last| last.item.as_ref())
+                    .filter(|last_item| status.item.as_ref().map(|item| &item.id) == Some(&last_item.id))
+                    .and_then(|last_item| last_item.liked)
+                    .filter(|_| self.liked_at.is_some_and(|at| at.elapsed() < LIKED_REFRESH_INTERVAL));
+
+                match (known_liked, status.item.as_mut()) {
+                    (Some(liked), Some(item)) => item.liked = Some(liked),
+                    _ => {
+                        // a failed lookup just leaves the liked state unknown
+                        if status.fetch_liked(&self.client).await.is_ok() {
+                            self.liked_at = Some(Instant::now());
+                        }
+                    }
+                }
+
+                Some(status)
+            },
+            None => None
+        };
+
+        self.failing = false;
+        let playing = maybe_status.as_ref().is_some_and(|status| status.is_playing);
+        if playing || !same_state(self.last.as_ref(), maybe_status.as_ref()) {
+            self.idle_interval = MIN_IDLE_INTERVAL;
+        } else {
+            self.back_off();
+        }
+
+        self.last = maybe_status.clone();
+        Ok(maybe_status)
+    }
+
+    /// How long to wait before the next poll.
+    pub fn delay(&self) -> Duration {
+        match &self.last {
+            Some(status) if status.is_playing && !self.failing => PLAYING_INTERVAL,
+            _ => self.idle_interval,
+        }
+    }
+
+    fn back_off(&mut self) {
+        self.idle_interval = (self.idle_interval * 2).min(MAX_IDLE_INTERVAL);
+    }
+}
+
+/// Compares everything but the progress, which moves on its own.
+fn same_state(last: Option<&Status>, next: Option<&Status>) -> bool {
+    match (last, next) {
+        (Some(last), Some(next)) => Status { progress_ms: next.progress_ms, ..last.clone() } == *next,
+        (None, None) => true,
+        _ => false,
+    }
+}
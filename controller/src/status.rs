@@ -0,0 +1,224 @@
+use rofify::template::{render, format_duration, release_year};
+use rspotify::AuthCodePkceSpotify;
+use rspotify::model::{AdditionalType, PlayableItem, CurrentPlaybackContext, RepeatState, Image, TrackId};
+use rspotify::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::Result;
+
+pub const DEFAULT_FORMAT: &str = "{artist} - {title} [{progress}/{duration}] {shuffle}{repeat}";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Track,
+    Episode,
+}
+
+/// What's currently playing: either a track or a podcast episode.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Item {
+    pub kind: ItemKind,
+    pub id: Option<String>,
+    pub uri: Option<String>,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub show: Option<String>,
+    /// When the album or episode came out.
+    #[serde(default)]
+    pub year: Option<u32>,
+    pub duration_ms: u64,
+    pub cover_url: Option<String>,
+    /// The album or show the cover belongs to, which covers are cached by.
+    #[serde(default)]
+    pub cover_id: Option<String>,
+    /// Only known for tracks, since episodes can't be liked.
+    pub liked: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DeviceStatus {
+    pub id: Option<String>,
+    pub name: String,
+    pub kind: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub item: Option<Item>,
+    pub is_playing: bool,
+    pub progress_ms: u64,
+    pub shuffle: bool,
+    pub repeat: RepeatState,
+    pub device: DeviceStatus,
+    pub volume: Option<u32>,
+}
+
+impl Status {
+    /// Gets the current playback state, or `None` if nothing is playing.
+    pub async fn fetch(client: &AuthCodePkceSpotify) -> Result<Option<Status>> {
+        let mut maybe_status = Self::fetch_unliked(client).await?;
+
+        if let Some(status) = maybe_status.as_mut() {
+            status.fetch_liked(client).await?;
+        }
+
+        Ok(maybe_status)
+    }
+
+    /// Same as [`Status::fetch`] but without the extra request needed to know
+    /// whether the track is liked, so `liked` is always `None`.
+    pub async fn fetch_unliked(client: &AuthCodePkceSpotify) -> Result<Option<Status>> {
+        let maybe_context = client.current_playback(
+            None,
+            Some([
+                &AdditionalType::Track,
+                &AdditionalType::Episode
+            ])
+        ).await?;
+
+        Ok(maybe_context.map(Status::from))
+    }
+
+    /// Looks up whether the current track is in the user's liked songs.
+    pub async fn fetch_liked(&mut self, client: &AuthCodePkceSpotify) -> Result<()> {
+        if let Some(item) = self.item.as_mut() {
+            if let (ItemKind::Track, Some(id)) = (item.kind, &item.id) {
+                let id = TrackId::from_id(id.clone())?;
+                item.liked = client
+                    .current_user_saved_tracks_contains([id])
+                    .await?
+                    .first()
+                    .copied();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fills in the placeholders of a `--format` template.
+    pub fn format(&self, template: &str) -> String {
+        render(template, |key| self.placeholder(key))
+    }
+
+    pub fn placeholder(&self, key: &str) -> Option<String> {
+        let item = self.item.as_ref();
+
+        let value = match key {
+            key if Item::PLACEHOLDERS.contains(&key) => match item {
+                Some(item) => return item.placeholder(key),
+                None => String::new(),
+            },
+            "progress" => format_duration(self.progress_ms),
+            "state" => if self.is_playing { "playing" } else { "paused" }.to_string(),
+            "shuffle" => if self.shuffle { "🔀" } else { "" }.to_string(),
+            "repeat" => match self.repeat {
+                RepeatState::Off => "",
+                RepeatState::Context => "🔁",
+                RepeatState::Track => "🔂",
+            }.to_string(),
+            "device" => self.device.name.clone(),
+            "volume" => self.volume.map(|volume| volume.to_string()).unwrap_or_default(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+impl Item {
+    const PLACEHOLDERS: [&'static str; 10] = [
+        "title", "artist", "artists", "album", "show", "kind", "year", "duration", "liked", "uri",
+    ];
+
+    /// Fills in the item's part of a template; see [`Status::placeholder`].
+    pub fn placeholder(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "title" => self.title.clone(),
+            "artist" | "artists" => self.artists.join(", "),
+            "album" => self.album.clone().unwrap_or_default(),
+            "show" => self.show.clone().unwrap_or_default(),
+            "kind" => match self.kind {
+                ItemKind::Track => "track".to_string(),
+                ItemKind::Episode => "episode".to_string(),
+            },
+            "year" => self.year.map(|year| year.to_string()).unwrap_or_default(),
+            "duration" => format_duration(self.duration_ms),
+            "liked" => match self.liked {
+                Some(true) => "♥".to_string(),
+                _ => String::new(),
+            },
+            "uri" => self.uri.clone().unwrap_or_default(),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+impl From<CurrentPlaybackContext> for Status {
+    fn from(context: CurrentPlaybackContext) -> Self {
+        let item = context.item.map(|item| match item {
+            PlayableItem::Track(track) => Item {
+                kind: ItemKind::Track,
+                id: track.id.as_ref().map(|id| id.id().to_string()),
+                uri: track.id.as_ref().map(|id| id.uri()),
+                title: track.name,
+                artists: track.artists.into_iter().map(|artist| artist.name).collect(),
+                album: Some(track.album.name),
+                show: None,
+                year: track.album.release_date.as_deref().and_then(release_year),
+                duration_ms: milliseconds(track.duration),
+                cover_url: largest_image_url(&track.album.images),
+                cover_id: track.album.id.as_ref().map(|id| id.id().to_string()),
+                liked: None,
+            },
+            PlayableItem::Episode(episode) => {
+                // episodes mostly share their show's cover
+                let (cover_url, cover_id) = match largest_image_url(&episode.show.images) {
+                    Some(url) => (Some(url), episode.show.id.id().to_string()),
+                    None => (largest_image_url(&episode.images), episode.id.id().to_string()),
+                };
+
+                Item {
+                    kind: ItemKind::Episode,
+                    id: Some(episode.id.id().to_string()),
+                    uri: Some(episode.id.uri()),
+                    title: episode.name,
+                    artists: vec![episode.show.publisher],
+                    album: None,
+                    show: Some(episode.show.name),
+                    year: release_year(&episode.release_date),
+                    duration_ms: milliseconds(episode.duration),
+                    cover_url,
+                    cover_id: Some(cover_id),
+                    liked: None,
+                }
+            },
+        });
+
+        Self {
+            item,
+            is_playing: context.is_playing,
+            progress_ms: context.progress.map(milliseconds).unwrap_or_default(),
+            shuffle: context.shuffle_state,
+            repeat: context.repeat_state,
+            device: DeviceStatus {
+                id: context.device.id,
+                name: context.device.name,
+                kind: <&str>::from(context.device._type).to_string(),
+            },
+            volume: context.device.volume_percent,
+        }
+    }
+}
+
+// spotify hands out images largest first
+fn largest_image_url(images: &[Image]) -> Option<String> {
+    images.first().map(|image| image.url.clone())
+}
+
+fn milliseconds(duration: chrono::Duration) -> u64 {
+    duration.num_milliseconds().max(0) as u64
+}
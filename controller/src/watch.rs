@@ -0,0 +1,268 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rspotify::AuthCodePkceSpotify;
+use rspotify::model::RepeatState;
+use serde::Serialize;
+
+use crate::Result;
+use crate::poll::Poller;
+use crate::status::{Status, Item, DeviceStatus};
+
+/// How far the progress can drift from where it should be between polls
+/// before it counts as a seek.
+const SEEK_TOLERANCE: Duration = Duration::from_secs(3);
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    TrackChanged { item: Option<Item> },
+    Paused,
+    Resumed,
+    Seeked { progress_ms: u64 },
+    DeviceChanged { device: Option<DeviceStatus> },
+    VolumeChanged { volume: Option<u32> },
+    ShuffleChanged { shuffle: bool },
+    RepeatChanged { repeat: RepeatState },
+}
+
+/// Polls the playback state and works out what changed between polls.
+pub struct Watcher {
+    poller: Poller,
+    last: Option<Status>,
+    /// When the last successful poll was.
+    polled_at: Option<Instant>,
+    /// Whether a poll has been tried yet, successful or not.
+    polled: bool,
+}
+
+impl Watcher {
+    pub fn new(client: Arc<AuthCodePkceSpotify>) -> Self {
+        Self {
+            poller: Poller::new(client),
+            last: None,
+            polled_at: None,
+            polled: false,
+        }
+    }
+
+    /// Waits until the next poll is due, then returns what changed since the
+    /// last one along with the new state. The first call polls straight away
+    /// and reports everything as having changed from nothing.
+    pub async fn next(&mut self) -> Result<(Vec<Event>, Option<Status>)> {
+        // failed polls wait too, so a loop around this doesn't hammer spotify
+        // while it's unreachable
+        if self.polled {
+            tokio::time::sleep(self.poller.delay()).await;
+        }
+        self.polled = true;
+
+        let status = self.poller.poll().await?;
+        let elapsed = self.polled_at.map(|at| at.elapsed()).unwrap_or_default();
+        let events = diff(self.last.as_ref(), status.as_ref(), elapsed);
+
+        self.last = status.clone();
+        self.polled_at = Some(Instant::now());
+        Ok((events, status))
+    }
+}
+
+/// Prints a line of JSON for every change in the playback state, until the
+/// process is killed.
+pub async fn run(client: Arc<AuthCodePkceSpotify>) {
+    let mut watcher = Watcher::new(client);
+
+    loop {
+        match watcher.next().await {
+            Ok((events, _)) => for event in events {
+                println!("{}", serde_json::to_string(&event).unwrap());
+            },
+            Err(error) => eprintln!("Failed to get playback status: {error}"),
+        }
+    }
+}
+
+/// The events that get from `last` to `next`, `elapsed` apart. Nothing
+/// playing counts as paused with no item and no device.
+pub fn diff(last: Option<&Status>, next: Option<&Status>, elapsed: Duration) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    let last_item = last.and_then(|status| status.item.as_ref());
+    let next_item = next.and_then(|status| status.item.as_ref());
+    let item_changed = last_item.map(|item| &item.id) != next_item.map(|item| &item.id);
+    if item_changed {
+        events.push(Event::TrackChanged { item: next_item.cloned() });
+    }
+
+    let last_device = last.map(|status| &status.device);
+    let next_device = next.map(|status| &status.device);
+    if last_device.map(|device| &device.id) != next_device.map(|device| &device.id) {
+        events.push(Event::DeviceChanged { device: next_device.cloned() });
+    }
+
+    let was_playing = last.is_some_and(|status| status.is_playing);
+    let is_playing = next.is_some_and(|status| status.is_playing);
+    match (was_playing, is_playing) {
+        (true, false) => events.push(Event::Paused),
+        (false, true) => events.push(Event::Resumed),
+        _ => (),
+    }
+
+    if let (Some(last), Some(next)) = (last, next) {
+        if !item_changed {
+            let tolerance = SEEK_TOLERANCE.as_millis() as u64;
+            let expected = last.progress_ms + if last.is_playing { elapsed.as_millis() as u64 } else { 0 };
+            let duration = next_item.map(|item| item.duration_ms).unwrap_or_default();
+            // a track on repeat starting over isn't a seek
+            let looped = expected + tolerance >= duration && next.progress_ms < tolerance;
+
+            if !looped && next.progress_ms.abs_diff(expected) > tolerance {
+                events.push(Event::Seeked { progress_ms: next.progress_ms });
+            }
+        }
+
+        if last.volume != next.volume {
+            events.push(Event::VolumeChanged { volume: next.volume });
+        }
+        if last.shuffle != next.shuffle {
+            events.push(Event::ShuffleChanged { shuffle: next.shuffle });
+        }
+        if last.repeat != next.repeat {
+            events.push(Event::RepeatChanged { repeat: next.repeat });
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::ItemKind;
+
+    fn item(id: &str, duration_ms: u64) -> Item {
+        Item {
+            kind: ItemKind::Track,
+            id: Some(id.to_string()),
+            uri: None,
+            title: id.to_string(),
+            artists: Vec::new(),
+            album: None,
+            show: None,
+            year: None,
+            duration_ms,
+            cover_url: None,
+            cover_id: None,
+            liked: None,
+        }
+    }
+
+    fn status(item: Item, progress_ms: u64) -> Status {
+        Status {
+            item: Some(item),
+            is_playing: true,
+            progress_ms,
+            shuffle: false,
+            repeat: RepeatState::Off,
+            device: DeviceStatus {
+                id: Some("device".to_string()),
+                name: "Device".to_string(),
+                kind: "Computer".to_string(),
+            },
+            volume: Some(50),
+        }
+    }
+
+    #[test]
+    fn first_poll_reports_everything() {
+        let next = status(item("a", 200_000), 10_000);
+        let events = diff(None, Some(&next), Duration::ZERO);
+
+        assert_eq!(events, vec![
+            Event::TrackChanged { item: next.item.clone() },
+            Event::DeviceChanged { device: Some(next.device.clone()) },
+            Event::Resumed,
+        ]);
+    }
+
+    #[test]
+    fn nothing_playing_on_first_poll_reports_nothing() {
+        assert!(diff(None, None, Duration::ZERO).is_empty());
+    }
+
+    #[test]
+    fn progress_within_tolerance_is_not_a_seek() {
+        let last = status(item("a", 200_000), 10_000);
+        let next = status(item("a", 200_000), 12_500);
+
+        assert!(diff(Some(&last), Some(&next), Duration::from_secs(1)).is_empty());
+    }
+
+    #[test]
+    fn progress_past_tolerance_is_a_seek() {
+        let last = status(item("a", 200_000), 10_000);
+        let next = status(item("a", 200_000), 60_000);
+
+        assert_eq!(
+            diff(Some(&last), Some(&next), Duration::from_secs(1)),
+            vec![Event::Seeked { progress_ms: 60_000 }]
+        );
+    }
+
+    #[test]
+    fn paused_progress_doesnt_advance() {
+        let mut last = status(item("a", 200_000), 10_000);
+        last.is_playing = false;
+        let mut next = last.clone();
+        next.progress_ms = 10_500;
+
+        assert!(diff(Some(&last), Some(&next), Duration::from_secs(30)).is_empty());
+    }
+
+    #[test]
+    fn repeat_starting_over_is_not_a_seek() {
+        let mut last = status(item("a", 200_000), 199_000);
+        last.repeat = RepeatState::Track;
+        let mut next = last.clone();
+        next.progress_ms = 1_000;
+
+        assert!(diff(Some(&last), Some(&next), Duration::from_secs(2)).is_empty());
+    }
+
+    #[test]
+    fn new_track_is_not_a_seek() {
+        let last = status(item("a", 200_000), 100_000);
+        let next = status(item("b", 200_000), 0);
+
+        assert_eq!(
+            diff(Some(&last), Some(&next), Duration::from_secs(1)),
+            vec![Event::TrackChanged { item: next.item.clone() }]
+        );
+    }
+
+    #[test]
+    fn stopping_pauses_and_clears_the_track_and_device() {
+        let last = status(item("a", 200_000), 100_000);
+
+        assert_eq!(diff(Some(&last), None, Duration::from_secs(1)), vec![
+            Event::TrackChanged { item: None },
+            Event::DeviceChanged { device: None },
+            Event::Paused,
+        ]);
+    }
+
+    #[test]
+    fn settings_changes_are_reported() {
+        let last = status(item("a", 200_000), 10_000);
+        let mut next = status(item("a", 200_000), 11_000);
+        next.volume = Some(80);
+        next.shuffle = true;
+        next.repeat = RepeatState::Context;
+
+        assert_eq!(diff(Some(&last), Some(&next), Duration::from_secs(1)), vec![
+            Event::VolumeChanged { volume: Some(80) },
+            Event::ShuffleChanged { shuffle: true },
+            Event::RepeatChanged { repeat: RepeatState::Context },
+        ]);
+    }
+}
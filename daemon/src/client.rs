@@ -0,0 +1,35 @@
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use crate::{Error, Result, socket_path};
+use crate::protocol::{self, Command, Reply, Request, Response, PROTOCOL_VERSION};
+
+/// Sends `command` to the daemon and waits for its reply.
+///
+/// Fails with [`Error::NotRunning`] when there's no daemon to talk to, and
+/// with [`Error::Version`] when there is one but it's from another version
+/// of rofify; either way the caller can do the work itself instead.
+pub async fn send(command: Command) -> Result<Reply> {
+    let stream = UnixStream::connect(socket_path())
+        .await
+        .map_err(|_| Error::NotRunning)?;
+    let (reader, mut writer) = stream.into_split();
+
+    let mut request = serde_json::to_string(&Request::new(command))?;
+    request.push('\n');
+    writer.write_all(request.as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let version = protocol::version(&line)?;
+    if version != PROTOCOL_VERSION {
+        return Err(Error::Version(version, PROTOCOL_VERSION));
+    }
+
+    match serde_json::from_str::<Response>(&line)?.reply {
+        Reply::Error { message } => Err(Error::Daemon(message)),
+        Reply::UnsupportedVersion => Err(Error::Version(version, PROTOCOL_VERSION)),
+        reply => Ok(reply),
+    }
+}
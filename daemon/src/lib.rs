@@ -0,0 +1,40 @@
+pub mod client;
+pub mod protocol;
+pub mod server;
+
+use std::{env, io, result};
+use std::path::PathBuf;
+
+const SOCKET_NAME: &str = "rofify.sock";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Malformed message: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("The daemon isn't running.")]
+    NotRunning,
+    #[error("The daemon is already running.")]
+    AlreadyRunning,
+    #[error("The daemon speaks protocol version {0}, but this is version {1}.")]
+    Version(u32, u32),
+    #[error("{0}")]
+    Daemon(String),
+    #[error("{0}")]
+    Controller(#[from] controller::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// Where the daemon listens: in the user's runtime directory if there is one,
+/// otherwise a per-user name in the temp directory.
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join(SOCKET_NAME),
+        None => {
+            let user = env::var("USER").unwrap_or_default();
+            env::temp_dir().join(format!("rofify-{user}.sock"))
+        }
+    }
+}
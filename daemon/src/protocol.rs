@@ -0,0 +1,141 @@
+//! The daemon's protocol: the client writes one [`Request`] as a line of JSON,
+//! and the daemon answers with one [`Response`] line before closing the
+//! connection. Bump [`PROTOCOL_VERSION`] on any change that older clients or
+//! daemons couldn't make sense of.
+
+use controller::Action;
+use controller::status::Status;
+use serde::{Serialize, Deserialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The version of a request or response line, which is the only part
+/// guaranteed to parse across versions; 0 if there's none.
+pub fn version(line: &str) -> serde_json::Result<u32> {
+    let version = serde_json::from_str::<serde_json::Value>(line)?
+        .get("version")
+        .and_then(|version| version.as_u64())
+        .unwrap_or_default();
+    Ok(version as u32)
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Request {
+    pub version: u32,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+impl Request {
+    pub fn new(command: Command) -> Self {
+        Self { version: PROTOCOL_VERSION, command }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+pub enum Command {
+    Ping,
+    Control { action: Action },
+    Status,
+    /// Opens the menus, which show up wherever the daemon's display is.
+    Show,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Response {
+    pub version: u32,
+    #[serde(flatten)]
+    pub reply: Reply,
+}
+
+impl Response {
+    pub fn new(reply: Reply) -> Self {
+        Self { version: PROTOCOL_VERSION, reply }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "result", rename_all = "kebab-case")]
+pub enum Reply {
+    Ok,
+    Status { status: Option<Box<Status>> },
+    Error { message: String },
+    /// The request's version isn't one the daemon speaks.
+    UnsupportedVersion,
+}
+
+#[cfg(test)]
+mod tests {
+    use controller::status::DeviceStatus;
+    use rspotify::model::RepeatState;
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+
+    fn round_trip<T: Serialize + DeserializeOwned>(value: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let requests = [
+            Request::new(Command::Ping),
+            Request::new(Command::Control { action: Action::PlayPause }),
+            Request::new(Command::Status),
+            Request::new(Command::Show),
+        ];
+
+        for request in requests {
+            assert_eq!(round_trip(&request), request);
+        }
+    }
+
+    #[test]
+    fn replies_round_trip() {
+        let status = Status {
+            item: None,
+            is_playing: false,
+            progress_ms: 1_000,
+            shuffle: true,
+            repeat: RepeatState::Context,
+            device: DeviceStatus {
+                id: Some("device".to_string()),
+                name: "Desktop".to_string(),
+                kind: "Computer".to_string(),
+            },
+            volume: None,
+        };
+        let replies = [
+            Response::new(Reply::Ok),
+            Response::new(Reply::Status { status: Some(Box::new(status)) }),
+            Response::new(Reply::Status { status: None }),
+            Response::new(Reply::Error { message: "Nothing is playing".to_string() }),
+            Response::new(Reply::UnsupportedVersion),
+        ];
+
+        for reply in replies {
+            assert_eq!(round_trip(&reply), reply);
+        }
+    }
+
+    #[test]
+    fn request_format() {
+        let request = Request::new(Command::Control { action: Action::Next });
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"version":1,"command":"control","action":"next"}"#
+        );
+    }
+
+    #[test]
+    fn versions_of_unknown_messages() {
+        // a newer client may send commands this version doesn't know
+        let line = r#"{"version":2,"command":"rewind","seconds":10}"#;
+        assert!(serde_json::from_str::<Request>(line).is_err());
+        assert_eq!(version(line).unwrap(), 2);
+
+        assert_eq!(version(r#"{"command":"ping"}"#).unwrap(), 0);
+        assert!(version("not json").is_err());
+    }
+}
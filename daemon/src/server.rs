@@ -0,0 +1,236 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use controller::Controller;
+use controller::poll::Poller;
+use controller::status::Status;
+use rofify::menu::MenuProgram;
+use rspotify::AuthCodePkceSpotify;
+use rspotify::prelude::*;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{Mutex, Notify, RwLock};
+
+use crate::{Error, Result, socket_path};
+use crate::protocol::{self, Command, Reply, Request, Response, PROTOCOL_VERSION};
+
+/// How long before the token expires to refresh it.
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+/// How long to wait between refreshes when the token's expiry is unknown or
+/// refreshing failed.
+const TOKEN_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+struct State {
+    client: Arc<AuthCodePkceSpotify>,
+    program: MenuProgram,
+    controller: Mutex<Controller>,
+    /// The last polled playback state; `None` until the first poll, and again
+    /// after anything that would make it stale.
+    status: RwLock<Option<Option<Status>>>,
+    repoll: Notify,
+}
+
+/// Listens for requests until interrupted, keeping `client`'s token fresh
+/// and the playback state cached in the meantime.
+pub async fn run(client: Arc<AuthCodePkceSpotify>, program: MenuProgram) -> Result<()> {
+    let path = socket_path();
+    if path.exists() {
+        // a live daemon answers, whereas a socket left behind by a crash doesn't
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(Error::AlreadyRunning);
+        }
+        fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    // the temp directory is shared, so keep other users off the socket
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+    let controller = Controller::new(Arc::clone(&client), program.clone()).await?;
+    let state = Arc::new(State {
+        client,
+        program,
+        controller: Mutex::new(controller),
+        status: RwLock::new(None),
+        repoll: Notify::new(),
+    });
+    tokio::spawn(keep_token_fresh(Arc::clone(&state.client)));
+    tokio::spawn(poll_status(Arc::clone(&state)));
+
+    let result = tokio::select! {
+        result = accept(listener, state) => result,
+        _ = tokio::signal::ctrl_c() => Ok(()),
+    };
+
+    fs::remove_file(&path)?;
+    result
+}
+
+async fn accept(listener: UnixListener, state: Arc<State>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = Arc::clone(&state);
+
+        tokio::spawn(async move {
+            if let Err(error) = handle(stream, state).await {
+                eprintln!("Failed to handle request: {error}");
+            }
+        });
+    }
+}
+
+async fn handle(stream: UnixStream, state: Arc<State>) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    // checked first, since newer requests may not parse at all
+    let reply = match protocol::version(&line) {
+        Ok(version) if version != PROTOCOL_VERSION => Reply::UnsupportedVersion,
+        _ => match serde_json::from_str::<Request>(&line) {
+            Ok(request) => state.reply(request.command).await,
+            Err(error) => Reply::Error { message: format!("Malformed request: {error}") },
+        },
+    };
+
+    let mut response = serde_json::to_string(&Response::new(reply))?;
+    response.push('\n');
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+impl State {
+    async fn reply(&self, command: Command) -> Reply {
+        match command {
+            Command::Ping => Reply::Ok,
+            Command::Control { action } => {
+                let result = self.controller.lock().await.act(&action).await;
+
+                // whatever the action did, the cached state no longer shows it
+                *self.status.write().await = None;
+                self.repoll.notify_one();
+
+                match result {
+                    Ok(_) => Reply::Ok,
+                    Err(error) => Reply::Error { message: error.to_string() },
+                }
+            },
+            Command::Status => {
+                if let Some(status) = self.status.read().await.clone() {
+                    return Reply::Status { status: status.map(Box::new) };
+                }
+
+                match Status::fetch(&self.client).await {
+                    Ok(status) => Reply::Status { status: status.map(Box::new) },
+                    Err(error) => Reply::Error { message: error.to_string() },
+                }
+            },
+            Command::Show => {
+                let client = Arc::clone(&self.client);
+                let program = self.program.clone();
+                tokio::spawn(async move { rofify::show(client, program).await });
+                Reply::Ok
+            },
+        }
+    }
+}
+
+async fn poll_status(state: Arc<State>) {
+    let mut poller = Poller::new(Arc::clone(&state.client));
+
+    loop {
+        match poller.poll().await {
+            Ok(status) => *state.status.write().await = Some(status),
+            Err(error) => eprintln!("Failed to get playback status: {error}"),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(poller.delay()) => (),
+            _ = state.repoll.notified() => (),
+        }
+    }
+}
+
+/// Refreshes the token a little before it expires, so requests never have to
+/// wait on a refresh.
+async fn keep_token_fresh(client: Arc<AuthCodePkceSpotify>) {
+    loop {
+        let expires_at = client
+            .get_token()
+            .lock()
+            .await
+            .unwrap()
+            .as_ref()
+            .and_then(|token| token.expires_at);
+
+        let until_refresh = expires_at
+            // already expired comes out negative, which means now
+            .map(|expires_at| (expires_at - chrono::Utc::now()).to_std().unwrap_or_default())
+            .map(|until_expiry| until_expiry.saturating_sub(TOKEN_REFRESH_MARGIN))
+            .unwrap_or(TOKEN_RETRY_INTERVAL);
+        tokio::time::sleep(until_refresh).await;
+
+        if let Err(error) = client.refresh_token().await {
+            eprintln!("Failed to refresh token: {error}");
+            tokio::time::sleep(TOKEN_RETRY_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rofify::config::Config;
+    use rspotify::{Credentials, OAuth};
+
+    use super::*;
+
+    fn state() -> Arc<State> {
+        let client = Arc::new(AuthCodePkceSpotify::new(Credentials::new_pkce("test"), OAuth::default()));
+        let controller = Controller::with_device(Arc::clone(&client), None, Config::default());
+
+        Arc::new(State {
+            client,
+            program: MenuProgram::Rofi,
+            controller: Mutex::new(controller),
+            status: RwLock::new(None),
+            repoll: Notify::new(),
+        })
+    }
+
+    async fn exchange(request: &str) -> Response {
+        let (client, server) = UnixStream::pair().unwrap();
+        let (reader, mut writer) = client.into_split();
+        writer.write_all(format!("{request}\n").as_bytes()).await.unwrap();
+
+        handle(server, state()).await.unwrap();
+
+        let mut line = String::new();
+        BufReader::new(reader).read_line(&mut line).await.unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[tokio::test]
+    async fn answers_its_own_version() {
+        let request = serde_json::to_string(&Request::new(Command::Ping)).unwrap();
+        assert_eq!(exchange(&request).await, Response::new(Reply::Ok));
+    }
+
+    #[tokio::test]
+    async fn rejects_other_versions() {
+        assert_eq!(
+            exchange(r#"{"version":2,"command":"rewind","seconds":10}"#).await,
+            Response::new(Reply::UnsupportedVersion)
+        );
+        assert_eq!(
+            exchange(r#"{"command":"ping"}"#).await,
+            Response::new(Reply::UnsupportedVersion)
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_malformed_requests() {
+        let response = exchange("not json").await;
+        assert!(matches!(response.reply, Reply::Error { .. }));
+    }
+}
@@ -0,0 +1,134 @@
+//! Exposes spotify connect playback on the D-Bus session bus as an MPRIS
+//! player, so desktop media widgets, lock screens and playerctl can see and
+//! control it wherever it's playing.
+
+pub mod player;
+
+use std::{result, sync::Arc};
+
+use controller::Controller;
+use controller::watch::{Event, Watcher};
+use rofify::menu::MenuProgram;
+use rspotify::AuthCodePkceSpotify;
+use zbus::{dbus_interface, Connection, ConnectionBuilder};
+
+use player::Player;
+
+pub const BUS_NAME: &str = "org.mpris.MediaPlayer2.rofify";
+pub const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("D-Bus error: {0}")]
+    Dbus(#[from] zbus::Error),
+    #[error("{0}")]
+    Controller(#[from] controller::Error),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// The `org.mpris.MediaPlayer2` interface, which is mostly about a window
+/// rofify doesn't have.
+struct Root;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn identity(&self) -> &str {
+        "Rofify"
+    }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<&str> {
+        vec!["spotify"]
+    }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
+
+/// Serves the player on the session bus, or on the bus at `address` if
+/// given (such as a private `dbus-daemon` for testing), until the process is
+/// killed.
+pub async fn run(client: Arc<AuthCodePkceSpotify>, program: MenuProgram, address: Option<&str>) -> Result<()> {
+    let builder = match address {
+        Some(address) => ConnectionBuilder::address(address)?,
+        None => ConnectionBuilder::session()?,
+    };
+    let controller = Controller::new(Arc::clone(&client), program).await?;
+    let connection = serve(builder, controller).await?;
+
+    publish_changes(&connection, client).await
+}
+
+/// Builds the connection with a player backed by `controller` served on it
+/// and its bus name claimed.
+pub async fn serve(builder: ConnectionBuilder<'_>, controller: Controller) -> Result<Connection> {
+    let connection = builder
+        .serve_at(OBJECT_PATH, Root)?
+        .serve_at(OBJECT_PATH, Player::new(controller))?
+        .name(BUS_NAME)?
+        .build()
+        .await?;
+
+    Ok(connection)
+}
+
+/// Keeps the player's state up to date, telling the bus about every change.
+async fn publish_changes(connection: &Connection, client: Arc<AuthCodePkceSpotify>) -> Result<()> {
+    let player_ref = connection
+        .object_server()
+        .interface::<_, Player>(OBJECT_PATH)
+        .await?;
+    let mut watcher = Watcher::new(client);
+
+    loop {
+        let (events, status) = match watcher.next().await {
+            Ok(changes) => changes,
+            Err(error) => {
+                eprintln!("Failed to get playback status: {error}");
+                continue;
+            }
+        };
+
+        player_ref.get_mut().await.update(status);
+        let player = player_ref.get().await;
+        let context = player_ref.signal_context();
+
+        for event in events {
+            match event {
+                Event::TrackChanged { .. } => {
+                    player.metadata_changed(context).await?;
+                    player.can_seek_changed(context).await?;
+                },
+                Event::Paused | Event::Resumed => player.playback_status_changed(context).await?,
+                Event::Seeked { progress_ms } => Player::seeked(context, player::microseconds(progress_ms)).await?,
+                Event::VolumeChanged { .. } => player.volume_changed(context).await?,
+                Event::ShuffleChanged { .. } => player.shuffle_changed(context).await?,
+                Event::RepeatChanged { .. } => player.loop_status_changed(context).await?,
+                Event::DeviceChanged { .. } => (),
+            }
+        }
+    }
+}
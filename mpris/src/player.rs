@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use controller::{Action, Controller};
+use controller::status::Status;
+use rspotify::model::RepeatState;
+use zbus::{dbus_interface, fdo, SignalContext};
+use zbus::zvariant::{ObjectPath, OwnedValue, Value};
+
+/// The spec keeps `/org/mpris` to itself apart from NoTrack, so track ids
+/// live under a path of rofify's own.
+const TRACK_PATH_PREFIX: &str = "/com/github/mmuldo/rofify/track/";
+const NO_TRACK_PATH: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// The `org.mpris.MediaPlayer2.Player` interface, backed by the same
+/// controller as `rofify control`.
+pub struct Player {
+    controller: Controller,
+    status: Option<Status>,
+    polled_at: Instant,
+}
+
+impl Player {
+    pub fn new(controller: Controller) -> Self {
+        Self {
+            controller,
+            status: None,
+            polled_at: Instant::now(),
+        }
+    }
+
+    pub fn update(&mut self, status: Option<Status>) {
+        self.status = status;
+        self.polled_at = Instant::now();
+    }
+
+    /// Where playback should be by now, going by the last poll.
+    fn position_ms(&self) -> u64 {
+        match &self.status {
+            Some(status) => {
+                let elapsed = if status.is_playing { self.polled_at.elapsed().as_millis() as u64 } else { 0 };
+                let position = status.progress_ms + elapsed;
+
+                match &status.item {
+                    Some(item) => position.min(item.duration_ms),
+                    None => position,
+                }
+            },
+            None => 0,
+        }
+    }
+
+    fn track_path(&self) -> ObjectPath<'static> {
+        let id = self.status
+            .as_ref()
+            .and_then(|status| status.item.as_ref())
+            .and_then(|item| item.id.as_ref());
+
+        // spotify ids are base62, which makes a valid path element, but a
+        // malformed one is no reason to bring the bridge down
+        id.and_then(|id| ObjectPath::try_from(format!("{TRACK_PATH_PREFIX}{id}")).ok())
+            .unwrap_or_else(|| ObjectPath::from_static_str_unchecked(NO_TRACK_PATH))
+    }
+
+    async fn act(&mut self, action: Action) -> fdo::Result<()> {
+        self.controller.act(&action).await.map_err(failed)
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    async fn next(&mut self) -> fdo::Result<()> {
+        self.act(Action::Next).await
+    }
+
+    async fn previous(&mut self) -> fdo::Result<()> {
+        self.act(Action::Previous).await
+    }
+
+    async fn play_pause(&mut self) -> fdo::Result<()> {
+        self.act(Action::PlayPause).await
+    }
+
+    async fn play(&self) -> fdo::Result<()> {
+        self.controller.play().await.map_err(failed)
+    }
+
+    async fn pause(&self) -> fdo::Result<()> {
+        self.controller.pause().await.map_err(failed)
+    }
+
+    /// Spotify connect has no real stop, so this pauses.
+    async fn stop(&self) -> fdo::Result<()> {
+        self.controller.pause().await.map_err(failed)
+    }
+
+    /// Seeks `offset` microseconds from the current position, skipping to the
+    /// next track when that would go past the end.
+    async fn seek(&mut self, offset: i64) -> fdo::Result<()> {
+        let duration_ms = self.status
+            .as_ref()
+            .and_then(|status| status.item.as_ref())
+            .map(|item| item.duration_ms);
+        let position_ms = (self.position_ms() as i64 + offset / 1000).max(0) as u64;
+
+        match duration_ms {
+            Some(duration_ms) if position_ms > duration_ms => self.act(Action::Next).await,
+            Some(_) => self.controller.seek(position_ms).await.map_err(failed),
+            None => Ok(()),
+        }
+    }
+
+    async fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        let duration_ms = self.status
+            .as_ref()
+            .and_then(|status| status.item.as_ref())
+            .map(|item| item.duration_ms as i64);
+
+        // the spec says to ignore stale track ids and out of range positions
+        match duration_ms {
+            Some(duration_ms) if track_id == self.track_path() && (0..=duration_ms * 1000).contains(&position) => {
+                self.controller.seek(position as u64 / 1000).await.map_err(failed)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    async fn open_uri(&self, uri: &str) -> fdo::Result<()> {
+        self.controller.play_uri(uri).await.map_err(failed)
+    }
+
+    #[dbus_interface(signal)]
+    pub async fn seeked(context: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &str {
+        match &self.status {
+            Some(status) if status.is_playing => "Playing",
+            Some(_) => "Paused",
+            None => "Stopped",
+        }
+    }
+
+    #[dbus_interface(property)]
+    fn loop_status(&self) -> &str {
+        match self.status.as_ref().map(|status| status.repeat) {
+            Some(RepeatState::Track) => "Track",
+            Some(RepeatState::Context) => "Playlist",
+            _ => "None",
+        }
+    }
+
+    #[dbus_interface(property)]
+    async fn set_loop_status(&mut self, loop_status: String) -> fdo::Result<()> {
+        let repeat = match loop_status.as_str() {
+            "None" => RepeatState::Off,
+            "Track" => RepeatState::Track,
+            "Playlist" => RepeatState::Context,
+            _ => return Err(fdo::Error::InvalidArgs(format!("Unknown loop status {loop_status:?}"))),
+        };
+
+        self.controller.set_repeat(repeat).await.map_err(failed)?;
+        if let Some(status) = self.status.as_mut() {
+            status.repeat = repeat;
+        }
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    /// Spotify only plays at normal speed, so other rates are ignored.
+    #[dbus_interface(property)]
+    fn set_rate(&self, _rate: f64) {}
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn shuffle(&self) -> bool {
+        self.status.as_ref().is_some_and(|status| status.shuffle)
+    }
+
+    #[dbus_interface(property)]
+    async fn set_shuffle(&mut self, shuffle: bool) -> fdo::Result<()> {
+        self.controller.set_shuffle(shuffle).await.map_err(failed)?;
+        if let Some(status) = self.status.as_mut() {
+            status.shuffle = shuffle;
+        }
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        self.status
+            .as_ref()
+            .and_then(|status| status.volume)
+            .map(|volume| volume as f64 / 100.0)
+            .unwrap_or_default()
+    }
+
+    #[dbus_interface(property)]
+    async fn set_volume(&mut self, volume: f64) -> fdo::Result<()> {
+        let volume_percent = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+
+        self.controller.set_volume(volume_percent).await.map_err(failed)?;
+        if let Some(status) = self.status.as_mut() {
+            status.volume = Some(volume_percent.into());
+        }
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn position(&self) -> i64 {
+        microseconds(self.position_ms())
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let mut metadata = HashMap::new();
+        metadata.insert("mpris:trackid".to_string(), Value::from(self.track_path()).into());
+
+        if let Some(item) = self.status.as_ref().and_then(|status| status.item.as_ref()) {
+            metadata.insert("mpris:length".to_string(), Value::from(microseconds(item.duration_ms)).into());
+            metadata.insert("xesam:title".to_string(), Value::from(item.title.clone()).into());
+            metadata.insert("xesam:artist".to_string(), Value::from(item.artists.clone()).into());
+
+            if let Some(album) = item.album.as_ref().or(item.show.as_ref()) {
+                metadata.insert("xesam:album".to_string(), Value::from(album.clone()).into());
+            }
+            if let Some(cover_url) = &item.cover_url {
+                metadata.insert("mpris:artUrl".to_string(), Value::from(cover_url.clone()).into());
+            }
+            if let Some(uri) = &item.uri {
+                metadata.insert("xesam:url".to_string(), Value::from(uri.clone()).into());
+            }
+        }
+
+        metadata
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        self.status.as_ref().is_some_and(|status| status.item.is_some())
+    }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+pub fn microseconds(ms: u64) -> i64 {
+    ms as i64 * 1000
+}
+
+fn failed(error: controller::Error) -> fdo::Error {
+    fdo::Error::Failed(error.to_string())
+}
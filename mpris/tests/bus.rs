@@ -0,0 +1,152 @@
+//! Serves the player on a private `dbus-daemon` and reads it back the way a
+//! media widget would.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+
+use controller::Controller;
+use controller::status::{DeviceStatus, Item, ItemKind, Status};
+use mpris::player::Player;
+use mpris::{BUS_NAME, OBJECT_PATH};
+use rofify::config::Config;
+use rspotify::{AuthCodePkceSpotify, Credentials, OAuth};
+use rspotify::model::RepeatState;
+use zbus::{Connection, ConnectionBuilder};
+use zbus::fdo::PropertiesProxy;
+use zbus::names::InterfaceName;
+use zbus::zvariant::{ObjectPath, OwnedValue};
+
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+/// A bus of its own, stopped when dropped.
+struct Bus {
+    daemon: Child,
+    address: String,
+}
+
+impl Bus {
+    /// Starts a bus, or `None` if `dbus-daemon` isn't installed.
+    fn start() -> Option<Bus> {
+        let spawned = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut daemon = match spawned {
+            Ok(daemon) => daemon,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+            Err(error) => panic!("Failed to start dbus-daemon: {error}"),
+        };
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+
+        Some(Bus { daemon, address: address.trim().to_string() })
+    }
+}
+
+impl Drop for Bus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+    }
+}
+
+fn controller() -> Controller {
+    let client = AuthCodePkceSpotify::new(Credentials::new_pkce("test"), OAuth::default());
+    Controller::with_device(Arc::new(client), None, Config::default())
+}
+
+fn status() -> Status {
+    Status {
+        item: Some(Item {
+            kind: ItemKind::Track,
+            id: Some("4uLU6hMCjMI75M1A2tKUQC".to_string()),
+            uri: Some("spotify:track:4uLU6hMCjMI75M1A2tKUQC".to_string()),
+            title: "Never Gonna Give You Up".to_string(),
+            artists: vec!["Rick Astley".to_string()],
+            album: Some("Whenever You Need Somebody".to_string()),
+            show: None,
+            year: Some(1987),
+            duration_ms: 213_000,
+            cover_url: None,
+            cover_id: None,
+            liked: None,
+        }),
+        is_playing: true,
+        progress_ms: 0,
+        shuffle: false,
+        repeat: RepeatState::Off,
+        device: DeviceStatus {
+            id: None,
+            name: "Test".to_string(),
+            kind: "Computer".to_string(),
+        },
+        volume: Some(50),
+    }
+}
+
+async fn property(connection: &Connection, interface: &'static str, name: &str) -> OwnedValue {
+    let properties = PropertiesProxy::builder(connection)
+        .destination(BUS_NAME)
+        .unwrap()
+        .path(OBJECT_PATH)
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+
+    properties
+        .get(InterfaceName::from_static_str_unchecked(interface), name)
+        .await
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_the_player_state() {
+    let bus = match Bus::start() {
+        Some(bus) => bus,
+        None => {
+            eprintln!("dbus-daemon isn't installed, skipping");
+            return;
+        },
+    };
+
+    let builder = ConnectionBuilder::address(bus.address.as_str()).unwrap();
+    let server = mpris::serve(builder, controller()).await.unwrap();
+    let client = ConnectionBuilder::address(bus.address.as_str()).unwrap().build().await.unwrap();
+
+    let identity: String = property(&client, ROOT_INTERFACE, "Identity").await.try_into().unwrap();
+    assert_eq!(identity, "Rofify");
+
+    let playback_status: String = property(&client, PLAYER_INTERFACE, "PlaybackStatus").await.try_into().unwrap();
+    assert_eq!(playback_status, "Stopped");
+
+    server
+        .object_server()
+        .interface::<_, Player>(OBJECT_PATH)
+        .await
+        .unwrap()
+        .get_mut()
+        .await
+        .update(Some(status()));
+
+    let playback_status: String = property(&client, PLAYER_INTERFACE, "PlaybackStatus").await.try_into().unwrap();
+    assert_eq!(playback_status, "Playing");
+
+    let metadata: HashMap<String, OwnedValue> = property(&client, PLAYER_INTERFACE, "Metadata").await.try_into().unwrap();
+    let title: String = metadata["xesam:title"].clone().try_into().unwrap();
+    let artists: Vec<String> = metadata["xesam:artist"].clone().try_into().unwrap();
+    let length: i64 = metadata["mpris:length"].clone().try_into().unwrap();
+    assert_eq!(title, "Never Gonna Give You Up");
+    assert_eq!(artists, vec!["Rick Astley"]);
+    assert_eq!(length, 213_000_000);
+
+    let track_id: ObjectPath = metadata["mpris:trackid"].clone().try_into().unwrap();
+    assert_eq!(track_id.as_str(), "/com/github/mmuldo/rofify/track/4uLU6hMCjMI75M1A2tKUQC");
+}
@@ -0,0 +1,87 @@
+//! Where rofify keeps its files, following the XDG base directory spec.
+//!
+//! Everything is resolved when it's asked for, so a packaged binary uses the
+//! directories of whoever runs it. Files are moved over from where older
+//! versions kept them the first time their new path is asked for.
+
+use std::{env, fs, io, process};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const APP_NAME: &str = "rofify";
+
+/// Keeps concurrent writes of the same file from sharing a temporary file.
+static WRITES: AtomicU64 = AtomicU64::new(0);
+
+/// `$XDG_CONFIG_HOME/rofify`, for the config file.
+pub fn config_dir() -> PathBuf {
+    base_dir("XDG_CONFIG_HOME", ".config").join(APP_NAME)
+}
+
+/// `$XDG_STATE_HOME/rofify`, for the spotify token and anything else rofify
+/// remembers between runs.
+pub fn state_dir() -> PathBuf {
+    base_dir("XDG_STATE_HOME", ".local/state").join(APP_NAME)
+}
+
+/// `$XDG_CACHE_HOME/rofify`, for cover art and anything else that can be
+/// fetched again.
+pub fn cache_dir() -> PathBuf {
+    base_dir("XDG_CACHE_HOME", ".cache").join(APP_NAME)
+}
+
+/// `$XDG_DATA_HOME/rofify`, for icons.
+pub fn data_dir() -> PathBuf {
+    base_dir("XDG_DATA_HOME", ".local/share").join(APP_NAME)
+}
+
+/// The home directory of whoever is running rofify.
+pub fn home_dir() -> PathBuf {
+    // without a home, paths end up relative to the working directory
+    env::var_os("HOME").map(PathBuf::from).unwrap_or_default()
+}
+
+/// Returns `path`, first moving whatever is at `legacy_path` there if only
+/// the old one exists.
+pub fn migrated(path: PathBuf, legacy_path: &Path) -> PathBuf {
+    if path != legacy_path && !path.exists() && legacy_path.exists() {
+        if let Err(error) = move_path(legacy_path, &path) {
+            eprintln!("Failed to move {} to {}: {error}", legacy_path.display(), path.display());
+        }
+    }
+    path
+}
+
+/// Writes to a temporary file next to `path` and renames it into place, so
+/// readers only ever see the old or the new contents.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temporary_name = path.file_name().unwrap_or_default().to_os_string();
+    temporary_name.push(format!(".{}-{}.tmp", process::id(), WRITES.fetch_add(1, Ordering::Relaxed)));
+    let temporary_path = path.with_file_name(temporary_name);
+
+    fs::write(&temporary_path, contents)?;
+    fs::rename(temporary_path, path)
+}
+
+fn base_dir(variable: &str, default: &str) -> PathBuf {
+    match env::var_os(variable).map(PathBuf::from) {
+        // the spec says relative paths are invalid and should be ignored
+        Some(dir) if dir.is_absolute() => dir,
+        _ => home_dir().join(default),
+    }
+}
+
+fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(from, to) {
+        // renaming doesn't work across file systems, but copying a file does
+        Err(_) if from.is_file() => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        },
+        result => result,
+    }
+}
@@ -1,53 +1,276 @@
-use notify_rust;
-use std::{env, path::{PathBuf, Path}};
+pub mod dirs;
+pub mod sink;
 
-const ICONS_DIR: &str = ".local/share/rofify/icons";
+use std::{env, error::Error, fs, path::PathBuf, sync::OnceLock};
+
+use image::DynamicImage;
+use serde::{Serialize, Deserialize};
+
+use sink::{Desktop, Notification, OnClick, Sink, SinkConfig};
+
+const ICONS_DIR: &str = "icons";
+/// Where icons were kept, relative to the home directory, before rofify
+/// followed `$XDG_DATA_HOME`.
+const LEGACY_ICONS_DIR: &str = ".local/share/rofify/icons";
 const APP_ICON: &str = "rofify.png";
-const COVER_ART_ICON: &str = "cover.png";
-
-pub fn notify(summary: &str, body: &str, icon: Option<String>) {
-    let mut notification = notify_rust::Notification::new();
-    let icon = if let Some(icon) = icon {
-        icon
-    } else {
-        match app_icon_path().into_os_string().into_string() {
-            Ok(icon) => icon,
-            // any garbage string (including "") works since it will result in
-            // the notification just not showing any icon
-            Err(_) => String::new()
+
+static CATEGORIES: OnceLock<Categories> = OnceLock::new();
+static SINKS: OnceLock<Sinks> = OnceLock::new();
+static ERROR_TEMPLATE: OnceLock<NotificationTemplate> = OnceLock::new();
+
+struct Sinks {
+    info: Box<dyn Sink>,
+    error: Box<dyn Sink>,
+}
+
+pub enum Icon {
+    Path(PathBuf),
+    /// Sent as the image-data hint, so nothing has to be saved for the
+    /// notification server to read back. Servers that ignore the hint show
+    /// `fallback` instead.
+    Image {
+        image: DynamicImage,
+        fallback: Option<PathBuf>,
+    },
+}
+
+/// A button on a notification, told apart from the others by `key`.
+#[derive(Clone, Copy)]
+pub struct Button {
+    pub key: &'static str,
+    pub label: &'static str,
+}
+
+/// Kinds of notification that replace the last one of their kind instead of
+/// stacking up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    NowPlaying,
+    Volume,
+    ShuffleRepeat,
+    Error,
+}
+
+impl Category {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::NowPlaying => "now-playing",
+            Self::Volume => "volume",
+            Self::ShuffleRepeat => "shuffle-repeat",
+            Self::Error => "error",
         }
-    };
+    }
+
+    /// Where the ID of the category's last notification is kept, so that
+    /// separate `rofify` processes replace each other's notifications too.
+    /// Falls back to a per-user name in the temp directory, which is shared.
+    fn replace_id_path(&self) -> PathBuf {
+        match env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir).join(format!("rofify-{}.id", self.name())),
+            None => {
+                let user = env::var("USER").unwrap_or_default();
+                env::temp_dir().join(format!("rofify-{user}-{}.id", self.name()))
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl From<Urgency> for notify_rust::Urgency {
+    fn from(urgency: Urgency) -> Self {
+        match urgency {
+            Urgency::Low => Self::Low,
+            Urgency::Normal => Self::Normal,
+            Urgency::Critical => Self::Critical,
+        }
+    }
+}
+
+/// How a category's notifications are shown; unset fields are left to the
+/// notification server.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct CategorySettings {
+    /// How long the notification stays up; 0 keeps it up until dismissed.
+    pub timeout_ms: Option<u32>,
+    pub urgency: Option<Urgency>,
+}
 
-    notification.summary(summary);
-    notification.body(body);
-    notification.icon(&icon);
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(default)]
+pub struct Categories {
+    pub now_playing: CategorySettings,
+    pub volume: CategorySettings,
+    pub shuffle_repeat: CategorySettings,
+    pub error: CategorySettings,
+}
 
-    match notification.show() {
-        Ok(_) => (),
-        Err(error) => {
-            eprintln!("Failed to send notification: {error}");
-            eprintln!("The original notification was:");
-            eprintln!("\t{summary}");
-            eprintln!("\t{body}");
-        },
+impl Categories {
+    fn get(&self, category: Category) -> CategorySettings {
+        match category {
+            Category::NowPlaying => self.now_playing,
+            Category::Volume => self.volume,
+            Category::ShuffleRepeat => self.shuffle_repeat,
+            Category::Error => self.error,
+        }
     }
 }
 
+/// Sets how each category is shown for the rest of the process. Only the
+/// first call has any effect.
+pub fn set_categories(categories: Categories) {
+    let _ = CATEGORIES.set(categories);
+}
+
+/// What a notification says, with `{placeholders}` for whoever sends it to
+/// fill in. Unset fields fall back to the defaults for that notification.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct NotificationTemplate {
+    pub summary: Option<String>,
+    pub body: Option<String>,
+    /// `false` turns the notification off entirely.
+    pub enabled: Option<bool>,
+}
+
+impl NotificationTemplate {
+    pub fn new(summary: &str, body: &str) -> Self {
+        Self {
+            summary: Some(summary.to_string()),
+            body: Some(body.to_string()),
+            enabled: Some(true),
+        }
+    }
+
+    /// Fills in whatever isn't set from `default`.
+    pub fn or(self, default: Self) -> Self {
+        Self {
+            summary: self.summary.or(default.summary),
+            body: self.body.or(default.body),
+            enabled: self.enabled.or(default.enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+}
+
+/// Sets what error notifications say for the rest of the process, with
+/// `{error}` standing for the error. Only the first call has any effect.
+pub fn set_error_template(template: NotificationTemplate) {
+    let _ = ERROR_TEMPLATE.set(template);
+}
+
+/// Sets where informational and error notifications go for the rest of the
+/// process. Only the first call has any effect.
+pub fn set_sinks(info: &SinkConfig, error: &SinkConfig) {
+    let _ = SINKS.set(Sinks { info: info.build(), error: error.build() });
+}
+
+pub fn notify(summary: &str, body: &str, icon: Option<Icon>) {
+    send(None, summary, body, icon, &[], None);
+}
+
+/// Like [`notify`], but replaces the last notification in `category`.
+pub fn notify_in(category: Category, summary: &str, body: &str, icon: Option<Icon>) {
+    send(Some(category), summary, body, icon, &[], None);
+}
+
+/// Like [`notify_in`], with `buttons` on the notification. `on_click` gets
+/// the key of the button clicked, if any is before the notification closes.
+///
+/// The click is waited for on a background thread, so the process has to
+/// stay up for the buttons to do anything.
+pub fn notify_with_buttons<F>(
+    category: Category,
+    summary: &str,
+    body: &str,
+    icon: Option<Icon>,
+    buttons: &[Button],
+    on_click: F
+)
+where
+    F: FnOnce(&str) + Send + 'static
+{
+    send(Some(category), summary, body, icon, buttons, Some(Box::new(on_click)));
+}
+
 pub fn enotify(body: &str) {
-    notify("Error", body, None);
+    let template = ERROR_TEMPLATE.get().cloned().unwrap_or_default();
+    if !template.is_enabled() {
+        return;
+    }
+
+    let fill = |text: Option<String>, default: &str| {
+        text.as_deref().unwrap_or(default).replace("{error}", body)
+    };
+    notify_in(Category::Error, &fill(template.summary, "Error"), &fill(template.body, "{error}"), None);
 }
 
+/// Notifies about `error`, following its sources for the whole story.
+pub fn enotify_error(context: &str, error: &dyn Error) {
+    enotify(&format!("{context}: {}", error_chain(error)));
+}
 
-pub fn icons_dir() -> PathBuf {
-    let path = Path::new(env!("HOME"));
-    let icons_dir = path.join(ICONS_DIR);
-    icons_dir
+/// `error` and each of its sources, skipping sources whose message the
+/// error before them already included.
+pub fn error_chain(error: &dyn Error) -> String {
+    let mut chain = error.to_string();
+    let mut last = chain.clone();
+    let mut source = error.source();
+
+    while let Some(error) = source {
+        let message = error.to_string();
+        if !last.contains(&message) {
+            chain.push_str(&format!("\ncaused by: {message}"));
+        }
+        last = message;
+        source = error.source();
+    }
+    chain
 }
 
-pub fn app_icon_path() -> PathBuf {
-    icons_dir().join(APP_ICON)
+fn send(
+    category: Option<Category>,
+    summary: &str,
+    body: &str,
+    icon: Option<Icon>,
+    buttons: &[Button],
+    on_click: Option<OnClick>
+) {
+    let notification = Notification { category, summary, body, icon, buttons };
+    let sinks = SINKS.get();
+    let sink = match (sinks, category) {
+        (Some(sinks), Some(Category::Error)) => sinks.error.as_ref(),
+        (Some(sinks), _) => sinks.info.as_ref(),
+        (None, _) => &Desktop,
+    };
+
+    sink.send(notification, on_click);
 }
 
-pub fn cover_art_icon_path() -> PathBuf {
-    icons_dir().join(COVER_ART_ICON)
+fn category_settings(category: Category) -> CategorySettings {
+    CATEGORIES.get().copied().unwrap_or_default().get(category)
+}
+
+fn last_id(category: Category) -> Option<u32> {
+    fs::read_to_string(category.replace_id_path())
+        .ok()
+        .and_then(|id| id.trim().parse().ok())
+}
+
+pub fn icons_dir() -> PathBuf {
+    dirs::data_dir().join(ICONS_DIR)
+}
+
+pub fn app_icon_path() -> PathBuf {
+    let legacy_path = dirs::home_dir().join(LEGACY_ICONS_DIR).join(APP_ICON);
+    dirs::migrated(icons_dir().join(APP_ICON), &legacy_path)
 }
@@ -0,0 +1,244 @@
+//! Where notifications end up: the desktop by default, or somewhere that
+//! works without one, like a terminal, a log file or a script.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use notify_rust::{Hint, Timeout};
+use serde::{Serialize, Deserialize};
+
+use crate::{Button, Category, Icon, app_icon_path, category_settings, last_id};
+
+/// What notify-rust reports as the action when a notification closes
+/// without a button being clicked.
+const CLOSED_ACTION: &str = "__closed";
+
+/// Handles a click on a notification button, given the button's key.
+pub type OnClick = Box<dyn FnOnce(&str) + Send>;
+
+pub struct Notification<'a> {
+    pub category: Option<Category>,
+    pub summary: &'a str,
+    pub body: &'a str,
+    pub icon: Option<Icon>,
+    pub buttons: &'a [Button],
+}
+
+impl Notification<'_> {
+    fn level(&self) -> &'static str {
+        match self.category {
+            Some(Category::Error) => "error",
+            _ => "info",
+        }
+    }
+}
+
+pub trait Sink: Send + Sync {
+    /// Sends `notification`, calling `on_click` if one of its buttons is
+    /// clicked. Sinks without buttons never call it.
+    fn send(&self, notification: Notification, on_click: Option<OnClick>);
+}
+
+/// Which sink to use, as written in the config.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    #[default]
+    Desktop,
+    Stdout,
+    /// A line of JSON per notification on stderr.
+    JsonStderr,
+    LogFile { path: PathBuf },
+    /// Runs `command` through sh with the notification in `ROFIFY_*`
+    /// environment variables.
+    Command { command: String },
+    Silent,
+}
+
+impl SinkConfig {
+    pub fn build(&self) -> Box<dyn Sink> {
+        match self {
+            Self::Desktop => Box::new(Desktop),
+            Self::Stdout => Box::new(Stdout),
+            Self::JsonStderr => Box::new(JsonStderr),
+            Self::LogFile { path } => Box::new(LogFile { path: path.clone() }),
+            Self::Command { command } => Box::new(ShellCommand { command: command.clone() }),
+            Self::Silent => Box::new(Silent),
+        }
+    }
+}
+
+pub struct Desktop;
+
+impl Sink for Desktop {
+    fn send(&self, notification: Notification, on_click: Option<OnClick>) {
+        let Notification { category, summary, body, icon, buttons } = notification;
+        let mut desktop_notification = notify_rust::Notification::new();
+        let icon_path = match &icon {
+            Some(Icon::Path(path)) | Some(Icon::Image { fallback: Some(path), .. }) => path.clone(),
+            _ => app_icon_path(),
+        };
+
+        desktop_notification.summary(summary);
+        desktop_notification.body(body);
+        // any garbage string (including "") works since it will result in the
+        // notification just not showing any icon
+        desktop_notification.icon(&icon_path.into_os_string().into_string().unwrap_or_default());
+
+        if let Some(Icon::Image { image, .. }) = icon {
+            // the spec has image data take precedence over the icon
+            match notify_rust::Image::try_from(image) {
+                Ok(image) => { desktop_notification.image_data(image); },
+                Err(error) => eprintln!("Failed to attach image to notification: {error}"),
+            }
+        }
+
+        for button in buttons {
+            desktop_notification.action(button.key, button.label);
+        }
+
+        if let Some(category) = category {
+            let settings = category_settings(category);
+
+            if let Some(timeout_ms) = settings.timeout_ms {
+                desktop_notification.timeout(match timeout_ms {
+                    0 => Timeout::Never,
+                    timeout_ms => Timeout::Milliseconds(timeout_ms),
+                });
+            }
+            if let Some(urgency) = settings.urgency {
+                desktop_notification.urgency(urgency.into());
+            }
+            if let Some(id) = last_id(category) {
+                desktop_notification.id(id);
+            }
+            // servers like dunst also replace by tag, which survives a lost ID
+            desktop_notification.hint(Hint::Custom("x-dunst-stack-tag".to_string(), format!("rofify-{}", category.name())));
+        }
+
+        match desktop_notification.show() {
+            Ok(handle) => {
+                if let Some(category) = category {
+                    if let Err(error) = fs::write(category.replace_id_path(), handle.id().to_string()) {
+                        eprintln!("Failed to remember notification id: {error}");
+                    }
+                }
+
+                if let Some(on_click) = on_click {
+                    thread::spawn(move || handle.wait_for_action(|key| {
+                        if key != CLOSED_ACTION {
+                            on_click(key);
+                        }
+                    }));
+                }
+            },
+            Err(error) => {
+                eprintln!("Failed to send notification: {error}");
+                eprintln!("The original notification was:");
+                eprintln!("\t{summary}");
+                eprintln!("\t{body}");
+            },
+        }
+    }
+}
+
+pub struct Stdout;
+
+impl Sink for Stdout {
+    fn send(&self, notification: Notification, _on_click: Option<OnClick>) {
+        println!("{}", one_line(&notification));
+    }
+}
+
+pub struct JsonStderr;
+
+#[derive(Serialize)]
+struct JsonNotification<'a> {
+    level: &'a str,
+    category: Option<&'a str>,
+    summary: &'a str,
+    body: &'a str,
+}
+
+impl Sink for JsonStderr {
+    fn send(&self, notification: Notification, _on_click: Option<OnClick>) {
+        let json = JsonNotification {
+            level: notification.level(),
+            category: notification.category.map(|category| category.name()),
+            summary: notification.summary,
+            body: notification.body,
+        };
+
+        match serde_json::to_string(&json) {
+            Ok(line) => eprintln!("{line}"),
+            Err(error) => eprintln!("Failed to serialize notification: {error}"),
+        }
+    }
+}
+
+pub struct LogFile {
+    path: PathBuf,
+}
+
+impl Sink for LogFile {
+    fn send(&self, notification: Notification, _on_click: Option<OnClick>) {
+        let line = format!(
+            "{} [{}] {}\n",
+            chrono::Local::now().to_rfc3339(),
+            notification.level(),
+            one_line(&notification)
+        );
+        let appended = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()));
+
+        if let Err(error) = appended {
+            eprintln!("Failed to write notification to {}: {error}", self.path.display());
+            eprint!("{line}");
+        }
+    }
+}
+
+pub struct ShellCommand {
+    command: String,
+}
+
+impl Sink for ShellCommand {
+    fn send(&self, notification: Notification, _on_click: Option<OnClick>) {
+        let spawned = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("ROFIFY_LEVEL", notification.level())
+            .env("ROFIFY_CATEGORY", notification.category.map(|category| category.name()).unwrap_or_default())
+            .env("ROFIFY_SUMMARY", notification.summary)
+            .env("ROFIFY_BODY", notification.body)
+            .stdin(Stdio::null())
+            .spawn();
+
+        match spawned {
+            // reaped in the background, like hooks
+            Ok(mut child) => {
+                thread::spawn(move || child.wait());
+            },
+            Err(error) => eprintln!("Failed to run notification command: {error}"),
+        }
+    }
+}
+
+pub struct Silent;
+
+impl Sink for Silent {
+    fn send(&self, _notification: Notification, _on_click: Option<OnClick>) {}
+}
+
+fn one_line(notification: &Notification) -> String {
+    match notification.body {
+        "" => notification.summary.to_string(),
+        body => format!("{}: {}", notification.summary, body.replace('\n', " / ")),
+    }
+}
@@ -6,10 +6,12 @@ use std::{
         Mutex
     },
     collections::HashMap, process::exit,
+    fs,
 };
 use url::Url;
 use async_trait::async_trait;
 use notify::{
+    dirs,
     notify,
     enotify
 };
@@ -28,7 +30,7 @@ use crate::{
         MenuProgram,
         MenuResult
     },
-    config::{Config, app_config_dir}
+    config::{Config, state_path}
 };
 use arboard::Clipboard;
 
@@ -140,7 +142,7 @@ async fn get_code(url: &str, program: MenuProgram) -> Result<String> {
         Ok(_) => {
             notify(
                 "Login",
-                &format!("Opened login page in your browser (login URL copied to clipboard)."),
+                "Opened login page in your browser (login URL copied to clipboard).",
                 None
             );
         }
@@ -207,6 +209,7 @@ async fn get_token(client: &mut AuthCodePkceSpotify, auth_url: &str, program: Me
         }
     }
 
+    fs::create_dir_all(dirs::state_dir())?;
     Ok(client.write_token_cache().await?)
 }
 
@@ -235,10 +238,10 @@ pub async fn auth(program: MenuProgram) -> Result<AuthCodePkceSpotify>{
 
     let mut spotify = AuthCodePkceSpotify::new(creds.clone(), oauth.clone());
     spotify.config.token_cached = true;
-    spotify.config.cache_path = app_config_dir().join(TOKEN_CACHE_FILE);
+    spotify.config.cache_path = state_path(TOKEN_CACHE_FILE);
 
     let auth_url = spotify.get_authorize_url(None)?;
-    let _ = get_token(&mut spotify, &auth_url, program).await?;
+    get_token(&mut spotify, &auth_url, program).await?;
     
     Ok(spotify)
 }
@@ -1,38 +1,874 @@
-use std::path::{PathBuf, Path};
+use std::{env, fs, io, result};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
 
-use serde::{Serialize, Deserialize};
+use notify::{Categories, NotificationTemplate, dirs, notify};
+use notify::sink::SinkConfig;
+use rspotify::model::SearchType;
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de;
+use serde_yaml::{Mapping, Value};
+use strum::IntoEnumIterator;
 
 use crate::menu::MenuProgram;
+use crate::menu::mode::Mode;
+use crate::menu::search::SEARCH_TYPES;
+use crate::migration::{self, CURRENT_VERSION};
 
-const CONFIG_DIR: &str = ".config";
-const APP_NAME: &str = "rofify";
-const CONFIG_NAME: &str = "config";
+const CONFIG_FILE: &str = "config.yml";
+/// Names another config file to use instead of the default one.
+const CONFIG_PATH_VARIABLE: &str = "ROFIFY_CONFIG";
+/// Followed by a field's name in upper case, overrides that field.
+const OVERRIDE_PREFIX: &str = "ROFIFY_";
+/// Where the token and other state were kept, relative to the home
+/// directory, before rofify followed the XDG base directories.
+const LEGACY_STATE_DIR: &str = ".config/rofify";
+/// Which version of the config file was last checked for unknown keys.
+const CHECKED_FILE: &str = "config_checked";
 const DEFAULT_PROGRAM: MenuProgram = MenuProgram::Rofi;
 const DEFAULT_REDIRECT_URI_PORT: u16 = 8888;
+const DEFAULT_NOTIFY_ON_PAUSE_RESUME: bool = false;
+const DEFAULT_API_ADDRESS: &str = "127.0.0.1";
+const DEFAULT_API_PORT: u16 = 8889;
+const DEFAULT_PARTY_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_PARTY_PORT: u16 = 8890;
+const DEFAULT_PARTY_MAX_PENDING: usize = 3;
+const DEFAULT_PARTY_COOLDOWN_SECS: u64 = 30;
+const DEFAULT_PARTY_SEARCH_COOLDOWN_SECS: u64 = 1;
+const DEFAULT_NOTIFICATION_BUTTONS: bool = true;
+pub const DEFAULT_COVER_SIZE: u32 = 300;
+pub const DEFAULT_COVER_CACHE_MAX_MB: u64 = 50;
+const DEFAULT_SEARCH_LIMIT: u32 = 25;
+const DEFAULT_LIKED_SONGS_LIMIT: u32 = 100;
 
+static PATH: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to convert config: {0}")]
+    Yaml(#[from] serde_yaml::Error),
+    #[error("Malformed config {}: {source}", path.display())]
+    Malformed {
+        path: PathBuf,
+        source: serde_yaml::Error,
+    },
+    #[error("Invalid value in {variable}: {message}")]
+    Override {
+        variable: String,
+        message: String,
+    },
+    #[error("Invalid value for {key}: {message}")]
+    Invalid {
+        key: String,
+        message: String,
+    },
+    #[error("Unknown config key: {0}")]
+    UnknownKey(String),
+    #[error("Unknown config keys: {}", .0.join(", "))]
+    UnknownKeys(Vec<String>),
+    #[error("The config is version {0}, but this rofify only knows up to version {1}.")]
+    TooNew(u32, u32),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// Settings are taken from, in order of precedence:
+///
+/// 1. the command line: `--config <path>` picks the file
+/// 2. the environment: `ROFIFY_CONFIG` picks the file when `--config`
+///    doesn't, and `ROFIFY_<FIELD>` overrides a field, like
+///    `ROFIFY_PROGRAM=DMenu`, `ROFIFY_DEVICE_ID=…` or
+///    `ROFIFY_REDIRECT_URI_PORT=8000`
+/// 3. the config file, `$XDG_CONFIG_HOME/rofify/config.yml` by default
+/// 4. the defaults
+///
+/// Overrides are parsed as YAML, so nested fields like `hooks` take flow
+/// syntax: `ROFIFY_HOOKS='{on_like: notify-send liked}'`. Empty ones are
+/// ignored, the same as `ROFIFY_CONFIG`.
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Config {
+    /// Which version of the config format the file is in. Older files are
+    /// upgraded when they're loaded.
+    pub version: Option<u32>,
     pub device_id: Option<String>,
     pub program: Option<MenuProgram>,
     pub redirect_uri_port: Option<u16>,
+    pub notify_on_pause_resume: Option<bool>,
+    pub api_address: Option<String>,
+    pub api_port: Option<u16>,
+    /// Bearer token the HTTP API requires; the API won't start without one.
+    pub api_token: Option<String>,
+    pub party_address: Option<String>,
+    pub party_port: Option<u16>,
+    /// How many suggestions each guest can have waiting for approval.
+    pub party_max_pending: Option<usize>,
+    /// How long each guest has to wait between suggestions.
+    pub party_cooldown_secs: Option<u64>,
+    /// How long each guest has to wait between searches.
+    pub party_search_cooldown_secs: Option<u64>,
+    pub hooks: Option<Hooks>,
+    /// Where to write now playing files for streaming overlays; nothing is
+    /// exported unless this is set.
+    pub export_dir: Option<PathBuf>,
+    /// Largest width and height, in pixels, to keep cover art at.
+    pub cover_size: Option<u32>,
+    /// How big the cover art cache can get before old covers are evicted.
+    pub cover_cache_max_mb: Option<u64>,
+    /// Timeout and urgency for each kind of notification that replaces its
+    /// last one in place.
+    pub notification_categories: Option<Categories>,
+    /// Whether `rofify notify` puts Next, Like and Pause buttons on now
+    /// playing notifications.
+    pub notification_buttons: Option<bool>,
+    /// Where informational notifications go: `desktop`, `stdout`,
+    /// `json_stderr`, `log_file` (with a `path`), `command` (with a
+    /// `command`) or `silent`.
+    pub info_sink: Option<SinkConfig>,
+    /// Where error notifications go, with the same choices as `info_sink`.
+    pub error_sink: Option<SinkConfig>,
+    pub notification_templates: Option<NotificationTemplates>,
+    pub list_limits: Option<ListLimits>,
+    pub mode_menu: Option<ModeMenuSettings>,
+    pub row_formats: Option<RowFormats>,
+    pub rofi_rows: Option<RofiRows>,
+}
+
+/// How each kind of item is listed in the menus, after the number every row
+/// starts with. `{key:<30}`, `{key:>6}` and `{key:^10}` pad a value to a
+/// width and `{key:.30}` cuts it off, to line up columns in monospace
+/// themes.
+///
+/// Tracks take `{name}`, `{artists}`, `{album}`, `{duration}`, `{year}`,
+/// `{popularity}`, `{explicit}` (`E` for explicit tracks) and
+/// `{track_number}`. Albums take `{name}`, `{artists}`, `{year}` and
+/// `{album_type}`. Playlists take `{name}`, `{owner}` and `{tracks}`.
+/// Artists take `{name}`, `{followers}`, `{popularity}` and `{genres}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RowFormats {
+    pub track: String,
+    pub album: String,
+    pub playlist: String,
+    pub artist: String,
+}
+
+impl Default for RowFormats {
+    fn default() -> Self {
+        Self {
+            track: "{name} | {album} | {artists}".to_string(),
+            album: "{name} | {artists}".to_string(),
+            playlist: "{name} | {owner}".to_string(),
+            artist: "{name}".to_string(),
+        }
+    }
+}
+
+/// How rofi shows the rows of `row_formats`; dmenu always gets them plain.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RofiRows {
+    /// Shows names in bold and everything else dimmed, with pango markup.
+    pub markup: bool,
+    /// Which columns rofi shows, like `1` or `1,3`, for row formats split
+    /// into columns by `column_separator`. Typing only filters on the
+    /// columns that are shown.
+    pub display_columns: Option<String>,
+    /// The regex that splits row formats into columns.
+    pub column_separator: String,
+}
+
+impl Default for RofiRows {
+    fn default() -> Self {
+        Self {
+            markup: true,
+            display_columns: None,
+            column_separator: "\t".to_string(),
+        }
+    }
+}
+
+/// What the menu `rofify show` opens with lists: shortcuts first, then the
+/// modes.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ModeMenuSettings {
+    /// Modes to list first, in this order; the rest follow in the usual
+    /// order.
+    pub order: Vec<Mode>,
+    pub hidden: Vec<Mode>,
+    pub shortcuts: Vec<Shortcut>,
+}
+
+impl ModeMenuSettings {
+    /// The modes to list, in order.
+    pub fn modes(&self) -> Vec<Mode> {
+        let mut modes = Vec::new();
+        for mode in self.order.iter().copied().chain(Mode::iter()) {
+            if !modes.contains(&mode) && !self.hidden.contains(&mode) {
+                modes.push(mode);
+            }
+        }
+        modes
+    }
+}
+
+/// An entry pinned to the top of the mode menu, like
+/// `{name: Focus, play: "spotify:playlist:…"}` or
+/// `{name: Lo-fi, search: {type: playlist, query: lofi}}`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Shortcut {
+    pub name: String,
+    #[serde(flatten)]
+    pub action: ShortcutAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutAction {
+    /// Plays a playlist, album, artist, show, track or episode by its
+    /// spotify URI.
+    Play(String),
+    /// Opens the results of a search.
+    Search {
+        #[serde(rename = "type", deserialize_with = "searchable_type")]
+        search_type: SearchType,
+        query: String,
+    },
+}
+
+/// Only the types of search there are result menus for.
+fn searchable_type<'de, D: Deserializer<'de>>(deserializer: D) -> result::Result<SearchType, D::Error> {
+    let search_type = SearchType::deserialize(deserializer)?;
+    match SEARCH_TYPES.contains(&search_type) {
+        true => Ok(search_type),
+        false => Err(de::Error::custom(format!(
+            "can't search for {}s, only artists, albums, tracks and playlists",
+            <&str>::from(search_type)
+        ))),
+    }
+}
+
+/// How many items a list shows before a "Load more…" row, either a number
+/// or `all`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case", try_from = "Value")]
+pub enum ListLimit {
+    All,
+    #[serde(untagged)]
+    Count(u32),
+}
+
+impl TryFrom<Value> for ListLimit {
+    type Error = String;
+
+    fn try_from(value: Value) -> result::Result<Self, String> {
+        let count = match &value {
+            Value::String(text) if text == "all" => return Ok(Self::All),
+            Value::Number(number) => number.as_u64().and_then(|count| u32::try_from(count).ok()),
+            _ => None,
+        };
+
+        count.map(Self::Count).ok_or_else(|| {
+            let found = serde_yaml::to_string(&value).unwrap_or_default();
+            format!("expected a number of items or `all`, found {}", found.trim())
+        })
+    }
+}
+
+/// The limit for each list in the menus; "Load more…" loads that many again.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct ListLimits {
+    pub search: ListLimit,
+    pub liked_songs: ListLimit,
+    pub playlists: ListLimit,
+}
+
+impl Default for ListLimits {
+    fn default() -> Self {
+        Self {
+            search: ListLimit::Count(DEFAULT_SEARCH_LIMIT),
+            liked_songs: ListLimit::Count(DEFAULT_LIKED_SONGS_LIMIT),
+            playlists: ListLimit::All,
+        }
+    }
+}
+
+/// What each notification says. Summaries and bodies take `{title}`,
+/// `{artists}`, `{album}`, `{show}`, `{year}`, `{duration}`, `{device}` and
+/// the other `status --format` placeholders, plus `{state}` for shuffle and
+/// repeat, `{volume}` for volume and `{error}` for errors.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NotificationTemplates {
+    pub on_change: NotificationTemplate,
+    pub pause: NotificationTemplate,
+    pub resume: NotificationTemplate,
+    pub like: NotificationTemplate,
+    pub already_liked: NotificationTemplate,
+    pub shuffle: NotificationTemplate,
+    pub repeat: NotificationTemplate,
+    pub volume: NotificationTemplate,
+    pub device: NotificationTemplate,
+    pub error: NotificationTemplate,
+}
+
+impl Default for NotificationTemplates {
+    fn default() -> Self {
+        let blank = NotificationTemplate::default;
+
+        Self {
+            on_change: blank(),
+            pause: blank(),
+            resume: blank(),
+            like: blank(),
+            already_liked: blank(),
+            shuffle: blank(),
+            repeat: blank(),
+            volume: blank(),
+            device: blank(),
+            error: blank(),
+        }.or_defaults()
+    }
+}
+
+impl NotificationTemplates {
+    /// Fills in whatever the config leaves out with the default text.
+    fn or_defaults(self) -> Self {
+        // only one of album and show is ever set
+        let collection = "{artists} - {album}{show}";
+        let track = "{title} | {album} | {artists}";
+
+        Self {
+            on_change: self.on_change.or(NotificationTemplate::new("{title}", collection)),
+            pause: self.pause.or(NotificationTemplate::new("Paused", collection)),
+            resume: self.resume.or(NotificationTemplate::new("Resumed", collection)),
+            like: self.like.or(NotificationTemplate::new("Added to liked songs:", track)),
+            already_liked: self.already_liked.or(NotificationTemplate::new("Already in liked songs:", track)),
+            shuffle: self.shuffle.or(NotificationTemplate::new("Shuffle", "{state}")),
+            repeat: self.repeat.or(NotificationTemplate::new("Repeat", "{state}")),
+            volume: self.volume.or(NotificationTemplate::new("Volume", "{volume}%")),
+            device: self.device.or(NotificationTemplate::new("Device set to {device}", "")),
+            error: self.error.or(NotificationTemplate::new("Error", "{error}")),
+        }
+    }
+}
+
+/// Shell commands run on playback events, with what's playing passed in
+/// `ROFIFY_*` environment variables.
+///
+/// Track, pause, resume and device hooks fire from change detection (`rofify
+/// notify`), so they also see changes made from other apps. Like and error
+/// hooks fire from rofify's own actions.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Hooks {
+    pub on_track_change: Option<String>,
+    pub on_pause: Option<String>,
+    pub on_resume: Option<String>,
+    pub on_device_change: Option<String>,
+    pub on_like: Option<String>,
+    /// Also gets the error message in `ROFIFY_ERROR`.
+    pub on_error: Option<String>,
 }
 
 impl Config {
-    pub fn load() -> Result<Config, confy::ConfyError> {
-        let mut config: Config = confy::load(APP_NAME, CONFIG_NAME)?;
+    /// The config file with the environment's overrides and the defaults
+    /// applied.
+    pub fn load() -> Result<Config> {
+        Self::load_with(Upgrade::Persist)
+    }
+
+    /// Like [`Config::load`], but an older config file is only upgraded in
+    /// memory, for commands that just look at the config.
+    pub fn load_unchanged() -> Result<Config> {
+        Self::load_with(Upgrade::InMemory)
+    }
+
+    fn load_with(upgrade: Upgrade) -> Result<Config> {
+        Ok(Self::file_config(upgrade)?.with_overrides(env::vars())?.with_defaults())
+    }
+
+    /// Just what's in the config file, for changing it without writing
+    /// overrides or defaults back.
+    pub fn load_file() -> Result<Config> {
+        Self::file_config(Upgrade::Persist)
+    }
+
+    fn file_config(upgrade: Upgrade) -> Result<Config> {
+        let path = config_path();
+        match read_file(&path, upgrade)? {
+            Some(text) => serde_yaml::from_str(&text).map_err(|source| Error::Malformed { path, source }),
+            None => Ok(Config { version: Some(CURRENT_VERSION), ..Default::default() }),
+        }
+    }
+
+    pub fn store(self) -> Result<()> {
+        write_file(&serde_yaml::to_string(&self)?)
+    }
+
+    /// The value of `key`, with dots between the names of nested fields
+    /// like `hooks.on_like`, in the config in effect.
+    pub fn get(key: &str) -> Result<Value> {
+        let keys = known_keys(key)?;
+        let mut value = serde_yaml::to_value(Self::load_unchanged()?)?;
+
+        for key in keys {
+            value = match value {
+                Value::Mapping(mut fields) => fields.remove(key).unwrap_or(Value::Null),
+                _ => Value::Null,
+            };
+        }
+        Ok(value)
+    }
+
+    /// Sets `key` in the config file to `value`, parsed as YAML, as long as
+    /// the config still loads afterwards. The file is written out again from
+    /// what it parses to, so comments and formatting in it are lost.
+    pub fn set(key: &str, value: &str) -> Result<()> {
+        let keys = known_keys(key)?;
+        let mut file = file_value(Upgrade::Persist)?;
+
+        insert_checked(&mut file, &keys, value).map_err(|message| Error::Invalid {
+            key: key.to_string(),
+            message,
+        })?;
+        write_file(&serde_yaml::to_string(&file)?)
+    }
+
+    /// Checks the config file for syntax errors, values of the wrong type and
+    /// unknown keys, and the environment for invalid overrides.
+    pub fn validate() -> Result<()> {
+        let config = Self::file_config(Upgrade::InMemory)?;
+
+        let unknown = unknown_keys(&file_value(Upgrade::InMemory)?, &reference(), "");
+        if !unknown.is_empty() {
+            return Err(Error::UnknownKeys(unknown));
+        }
+
+        config.with_overrides(env::vars())?;
+        Ok(())
+    }
+
+    fn with_defaults(mut self) -> Self {
+        self.version = Some(self.version.unwrap_or(CURRENT_VERSION));
+        self.program = Some(self.program.unwrap_or(DEFAULT_PROGRAM));
+        self.redirect_uri_port = Some(self.redirect_uri_port.unwrap_or(DEFAULT_REDIRECT_URI_PORT));
+        self.notify_on_pause_resume = Some(self.notify_on_pause_resume.unwrap_or(DEFAULT_NOTIFY_ON_PAUSE_RESUME));
+        self.api_address = Some(self.api_address.unwrap_or(DEFAULT_API_ADDRESS.to_string()));
+        self.api_port = Some(self.api_port.unwrap_or(DEFAULT_API_PORT));
+        self.party_address = Some(self.party_address.unwrap_or(DEFAULT_PARTY_ADDRESS.to_string()));
+        self.party_port = Some(self.party_port.unwrap_or(DEFAULT_PARTY_PORT));
+        self.party_max_pending = Some(self.party_max_pending.unwrap_or(DEFAULT_PARTY_MAX_PENDING));
+        self.party_cooldown_secs = Some(self.party_cooldown_secs.unwrap_or(DEFAULT_PARTY_COOLDOWN_SECS));
+        self.party_search_cooldown_secs = Some(self.party_search_cooldown_secs.unwrap_or(DEFAULT_PARTY_SEARCH_COOLDOWN_SECS));
+        self.hooks = Some(self.hooks.unwrap_or_default());
+        self.cover_size = Some(self.cover_size.unwrap_or(DEFAULT_COVER_SIZE));
+        self.cover_cache_max_mb = Some(self.cover_cache_max_mb.unwrap_or(DEFAULT_COVER_CACHE_MAX_MB));
+        self.notification_categories = Some(self.notification_categories.unwrap_or_default());
+        self.notification_buttons = Some(self.notification_buttons.unwrap_or(DEFAULT_NOTIFICATION_BUTTONS));
+        self.info_sink = Some(self.info_sink.unwrap_or_default());
+        self.error_sink = Some(self.error_sink.unwrap_or_default());
+        self.notification_templates = Some(self.notification_templates.unwrap_or_default().or_defaults());
+        self.list_limits = Some(self.list_limits.unwrap_or_default());
+        self.mode_menu = Some(self.mode_menu.unwrap_or_default());
+        self.row_formats = Some(self.row_formats.unwrap_or_default());
+        self.rofi_rows = Some(self.rofi_rows.unwrap_or_default());
+        self
+    }
+
+    fn with_overrides(self, variables: impl Iterator<Item = (String, String)>) -> Result<Config> {
+        let mut value = serde_yaml::to_value(&self)?;
+
+        for (variable, raw) in variables {
+            let field = match variable.strip_prefix(OVERRIDE_PREFIX) {
+                Some(field) if !raw.is_empty() => field.to_lowercase(),
+                _ => continue,
+            };
+            let known = matches!(&value, Value::Mapping(fields) if fields.contains_key(field.as_str()));
+            if !known {
+                continue;
+            }
+
+            insert_checked(&mut value, &[&field], &raw).map_err(|message| Error::Override { variable, message })?;
+        }
+
+        Ok(serde_yaml::from_value(value)?)
+    }
+}
+
+/// Whether an older config file is written back once it's upgraded, which
+/// only loading it to use it or changing it does.
+#[derive(Clone, Copy)]
+enum Upgrade {
+    Persist,
+    InMemory,
+}
+
+/// The config file's text, upgraded to the current version if it's older.
+fn read_file(path: &Path, upgrade: Upgrade) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(text) if text.trim().is_empty() => Ok(None),
+        Ok(text) => match upgrade {
+            Upgrade::Persist => migration::upgrade(path, text).map(Some),
+            Upgrade::InMemory => migration::upgraded(path, text).map(Some),
+        },
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+fn write_file(text: &str) -> Result<()> {
+    let path = config_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(dirs::write_atomically(&path, text.as_bytes())?)
+}
 
-        config.program = Some(config.program.unwrap_or(DEFAULT_PROGRAM));
-        config.redirect_uri_port = Some(config.redirect_uri_port.unwrap_or(DEFAULT_REDIRECT_URI_PORT));
-        Ok(config)
+/// The config file as it's written, without filling anything in.
+fn file_value(upgrade: Upgrade) -> Result<Value> {
+    let path = config_path();
+    match read_file(&path, upgrade)? {
+        Some(text) => serde_yaml::from_str(&text).map_err(|source| Error::Malformed { path, source }),
+        None => Ok(Value::Mapping(Mapping::from_iter([("version".into(), CURRENT_VERSION.into())]))),
     }
+}
+
+/// Notifies about keys in the config file that rofify doesn't know, which
+/// are otherwise ignored without a word. Status bars run rofify every
+/// second, so each version of the file is only checked once.
+pub fn warn_about_unknown_keys() {
+    let path = config_path();
+    let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => modified,
+        Err(_) => return,
+    };
+    let stamp = format!(
+        "{}\n{}",
+        path.display(),
+        modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+    );
+
+    let checked_path = state_path(CHECKED_FILE);
+    if fs::read_to_string(&checked_path).is_ok_and(|checked| checked == stamp) {
+        return;
+    }
+    let _ = fs::create_dir_all(dirs::state_dir())
+        .and_then(|_| fs::write(&checked_path, stamp));
+
+    let unknown = match file_value(Upgrade::InMemory) {
+        Ok(value) => unknown_keys(&value, &reference(), ""),
+        // loading the config reports these
+        Err(_) => return,
+    };
+
+    if !unknown.is_empty() {
+        notify("Unknown config keys, ignored:", &unknown.join(", "), None);
+    }
+}
+
+/// Every setting there is, for telling known keys from unknown ones.
+fn reference() -> Value {
+    serde_yaml::to_value(Config::default().with_defaults()).unwrap_or(Value::Null)
+}
+
+/// Whether `fields` is one of the sinks, whose fields depend on its type.
+fn is_tagged(fields: &Mapping) -> bool {
+    fields.contains_key("type")
+}
+
+fn known_keys(key: &str) -> Result<Vec<&str>> {
+    let keys: Vec<&str> = key.split('.').collect();
+    let mut reference = reference();
+
+    for key_part in &keys {
+        reference = match reference {
+            Value::Mapping(fields) if is_tagged(&fields) => return Ok(keys),
+            Value::Mapping(mut fields) if fields.contains_key(*key_part) => fields.remove(*key_part).unwrap_or_default(),
+            _ => return Err(Error::UnknownKey(key.to_string())),
+        };
+    }
+    Ok(keys)
+}
+
+fn unknown_keys(value: &Value, reference: &Value, prefix: &str) -> Vec<String> {
+    let (fields, reference_fields) = match (value, reference) {
+        (Value::Mapping(fields), Value::Mapping(reference_fields)) if !is_tagged(reference_fields) => {
+            (fields, reference_fields)
+        },
+        _ => return Vec::new(),
+    };
+    let mut unknown = Vec::new();
+
+    for (key, value) in fields {
+        let name = match key {
+            Value::String(name) => format!("{prefix}{name}"),
+            key => format!("{prefix}{}", serde_yaml::to_string(key).unwrap_or_default().trim()),
+        };
+        match reference_fields.get(key) {
+            Some(reference) => unknown.extend(unknown_keys(value, reference, &format!("{name}."))),
+            None => unknown.push(name),
+        }
+    }
+    unknown
+}
+
+/// Sets the field at `keys` to `raw` parsed as YAML, or failing that as a
+/// string, as long as the result is still a valid config.
+fn insert_checked(value: &mut Value, keys: &[&str], raw: &str) -> result::Result<(), String> {
+    let parsed = serde_yaml::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+    let mut first_error = None;
+
+    // strings that look like numbers, like some device IDs, still have to
+    // work for string fields
+    for candidate in [parsed, Value::String(raw.to_string())] {
+        let mut updated = value.clone();
+        insert(&mut updated, keys, candidate);
 
-    pub fn store(self) -> Result<(), confy::ConfyError> {
-        confy::store(APP_NAME, CONFIG_NAME, self)
+        match serde_yaml::from_value::<Config>(updated.clone()) {
+            Ok(_) => {
+                *value = updated;
+                return Ok(());
+            },
+            Err(error) => first_error = first_error.or(Some(error.to_string())),
+        }
     }
+    Err(first_error.unwrap_or_default())
 }
 
-pub fn app_config_dir() -> PathBuf {
-    let path = Path::new(env!("HOME"));
-    let home_config_dir = path.join(CONFIG_DIR);
-    home_config_dir.join(APP_NAME)
+fn insert(value: &mut Value, keys: &[&str], new: Value) {
+    let (key, rest) = match keys.split_first() {
+        Some(split) => split,
+        None => {
+            *value = new;
+            return;
+        },
+    };
+    if !value.is_mapping() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    if let Value::Mapping(fields) = value {
+        insert(fields.entry(Value::from(*key)).or_insert(Value::Null), rest, new);
+    }
+}
+
+/// Uses the config file at `path` for the rest of the process, over
+/// `ROFIFY_CONFIG` and the default. Only the first call has any effect.
+pub fn set_path(path: PathBuf) {
+    let _ = PATH.set(path);
+}
+
+/// Where the config file is read from and stored to.
+pub fn config_path() -> PathBuf {
+    resolve_path(PATH.get(), env::var_os(CONFIG_PATH_VARIABLE))
+}
+
+/// The config file `--config` names, otherwise the one `ROFIFY_CONFIG`
+/// does, otherwise the default.
+fn resolve_path(flag: Option<&PathBuf>, variable: Option<OsString>) -> PathBuf {
+    match (flag, variable) {
+        (Some(path), _) => path.clone(),
+        (None, Some(path)) if !path.is_empty() => PathBuf::from(path),
+        _ => dirs::config_dir().join(CONFIG_FILE),
+    }
+}
+
+/// Where rofify keeps `name` between runs, moved over from the config
+/// directory where older versions kept it.
+pub fn state_path(name: &str) -> PathBuf {
+    let legacy_path = dirs::home_dir().join(LEGACY_STATE_DIR).join(name);
+    dirs::migrated(dirs::state_dir().join(name), &legacy_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(variable, value)| (variable.to_string(), value.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn from_file() -> Config {
+        Config {
+            device_id: Some("file".to_string()),
+            redirect_uri_port: Some(9000),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flag_beats_variable_beats_default() {
+        let flag = PathBuf::from("/flag.yml");
+
+        assert_eq!(resolve_path(Some(&flag), Some("/variable.yml".into())), flag);
+        assert_eq!(resolve_path(None, Some("/variable.yml".into())), PathBuf::from("/variable.yml"));
+        assert_eq!(resolve_path(None, Some("".into())), dirs::config_dir().join(CONFIG_FILE));
+        assert_eq!(resolve_path(None, None), dirs::config_dir().join(CONFIG_FILE));
+    }
+
+    #[test]
+    fn environment_beats_file_beats_defaults() {
+        let config = from_file()
+            .with_overrides(variables(&[("ROFIFY_DEVICE_ID", "env")]))
+            .unwrap()
+            .with_defaults();
+
+        assert_eq!(config.device_id.as_deref(), Some("env"));
+        assert_eq!(config.redirect_uri_port, Some(9000));
+        assert_eq!(config.api_port, Some(DEFAULT_API_PORT));
+    }
+
+    #[test]
+    fn empty_and_unrelated_variables_are_ignored() {
+        let config = from_file()
+            .with_overrides(variables(&[
+                ("ROFIFY_DEVICE_ID", ""),
+                ("ROFIFY_CURRENT_DEVICE_ID", "hook"),
+                ("DEVICE_ID", "other"),
+            ]))
+            .unwrap();
+
+        assert_eq!(config.device_id.as_deref(), Some("file"));
+    }
+
+    #[test]
+    fn invalid_overrides_name_the_variable() {
+        let result = from_file().with_overrides(variables(&[("ROFIFY_REDIRECT_URI_PORT", "high")]));
+
+        assert!(matches!(result, Err(Error::Override { variable, .. }) if variable == "ROFIFY_REDIRECT_URI_PORT"));
+    }
+
+    #[test]
+    fn numeric_looking_device_ids_stay_strings() {
+        let mut value = serde_yaml::to_value(from_file()).unwrap();
+        insert_checked(&mut value, &["device_id"], "0123456789").unwrap();
+
+        let config: Config = serde_yaml::from_value(value).unwrap();
+        assert_eq!(config.device_id.as_deref(), Some("0123456789"));
+    }
+
+    #[test]
+    fn list_limits_are_counts_or_all() {
+        assert_eq!(serde_yaml::from_str::<ListLimit>("all").unwrap(), ListLimit::All);
+        assert_eq!(serde_yaml::from_str::<ListLimit>("25").unwrap(), ListLimit::Count(25));
+        assert_eq!(serde_yaml::to_string(&ListLimit::All).unwrap().trim(), "all");
+        assert_eq!(serde_yaml::to_string(&ListLimit::Count(25)).unwrap().trim(), "25");
+    }
+
+    #[test]
+    fn invalid_list_limits_say_what_was_found() {
+        for invalid in ["All", "-1", "2.5", "5000000000"] {
+            let error = ListLimit::try_from(serde_yaml::from_str::<Value>(invalid).unwrap()).unwrap_err();
+            assert_eq!(error, format!("expected a number of items or `all`, found {invalid}"));
+        }
+    }
+
+    #[test]
+    fn unknown_programs_are_refused() {
+        let mut value = serde_yaml::to_value(from_file()).unwrap();
+
+        assert!(insert_checked(&mut value, &["program"], "Wofi").is_err());
+        assert!(insert_checked(&mut value, &["program"], "DMenu").is_ok());
+        assert!(matches!(
+            serde_yaml::from_value::<Config>(value).unwrap().program,
+            Some(MenuProgram::DMenu)
+        ));
+    }
+
+    fn mode_menu(yaml: &str) -> serde_yaml::Result<ModeMenuSettings> {
+        serde_yaml::from_str(yaml)
+    }
+
+    #[test]
+    fn ordered_modes_come_first() {
+        let settings = mode_menu("order: [liked_songs, device]").unwrap();
+        let modes = settings.modes();
+
+        assert_eq!(modes[..2], [Mode::LikedSongs, Mode::Device]);
+        assert_eq!(modes.len(), Mode::iter().count());
+        assert_eq!(modes[2..], Mode::iter().filter(|mode| !settings.order.contains(mode)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn hidden_modes_are_left_out() {
+        let modes = mode_menu("{order: [device], hidden: [device, artist_search]}").unwrap().modes();
+
+        assert!(!modes.contains(&Mode::Device));
+        assert!(!modes.contains(&Mode::ArtistSearch));
+        assert_eq!(modes.len(), Mode::iter().count() - 2);
+    }
+
+    #[test]
+    fn default_modes_are_all_in_the_usual_order() {
+        assert_eq!(ModeMenuSettings::default().modes(), Mode::iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unknown_modes_are_refused() {
+        assert!(mode_menu("order: [podcasts]").is_err());
+        assert!(mode_menu("hidden: [Liked Songs]").is_err());
+    }
+
+    #[test]
+    fn shortcuts_only_search_for_what_has_menus() {
+        let settings = mode_menu("shortcuts: [{name: Lo-fi, search: {type: playlist, query: lofi}}]").unwrap();
+        assert!(matches!(
+            settings.shortcuts[0].action,
+            ShortcutAction::Search { search_type: SearchType::Playlist, .. }
+        ));
+
+        for search_type in ["show", "episode"] {
+            let yaml = format!("shortcuts: [{{name: Pods, search: {{type: {search_type}, query: news}}}}]");
+            let error = mode_menu(&yaml).unwrap_err().to_string();
+            assert!(error.contains(&format!("can't search for {search_type}s")), "{error}");
+        }
+    }
+
+    #[test]
+    fn known_keys_follow_nested_fields() {
+        assert_eq!(known_keys("device_id").unwrap(), ["device_id"]);
+        assert_eq!(known_keys("list_limits.search").unwrap(), ["list_limits", "search"]);
+
+        for unknown in ["colour", "list_limits.albums", "device_id.name"] {
+            assert!(matches!(known_keys(unknown), Err(Error::UnknownKey(key)) if key == unknown));
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_named_in_full() {
+        let file = serde_yaml::from_str("{device_id: abc, colour: red, list_limits: {search: 10, albums: 5}}").unwrap();
+
+        assert_eq!(unknown_keys(&file, &reference(), ""), ["colour", "list_limits.albums"]);
+    }
+
+    #[test]
+    fn set_writes_what_get_reads() {
+        notify::set_sinks(&SinkConfig::Silent, &SinkConfig::Silent);
+        let dir = env::temp_dir().join(format!("rofify-config-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join(CONFIG_FILE);
+        set_path(path.clone());
+
+        Config::set("device_id", "0123456789").unwrap();
+        Config::set("list_limits.search", "all").unwrap();
+        assert_eq!(Config::get("device_id").unwrap(), Value::from("0123456789"));
+        assert_eq!(Config::get("list_limits.search").unwrap(), Value::from("all"));
+
+        let written = fs::read_to_string(&path).unwrap();
+        assert!(matches!(Config::set("redirect_uri_port", "high"), Err(Error::Invalid { .. })));
+        assert!(matches!(Config::set("colour", "red"), Err(Error::UnknownKey(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), written);
+
+        // nothing is left behind from writing
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -1,16 +1,28 @@
 pub mod auth;
 pub mod config;
 pub mod menu;
+pub mod migration;
+pub mod template;
 
 use std::sync::Arc;
 
+use notify::enotify_error;
 use rspotify::AuthCodePkceSpotify;
 
+use config::Config;
 use menu::{Menu, MenuProgram, MenuResult};
 use menu::mode::ModeMenu;
 
 pub async fn show(client: Arc<AuthCodePkceSpotify>, program: MenuProgram) {
-    let mode_menu = Box::new(ModeMenu::new(Arc::clone(&client)));
+    // loaded once for all the menus shown
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(error) => {
+            enotify_error("Failed to load config", &error);
+            return;
+        }
+    };
+    let mode_menu = Box::new(ModeMenu::new(Arc::clone(&client), config));
     let mut menu_stack: Vec<Box<dyn Menu>> = vec![mode_menu];
 
     while let Some(menu) = menu_stack.pop() {
@@ -6,7 +6,8 @@ use std::{
 use async_trait::async_trait;
 use notify::{
     notify,
-    enotify
+    enotify,
+    enotify_error
 };
 use rspotify::{
     prelude::*,
@@ -15,6 +16,7 @@ use rspotify::{
 };
 
 use crate::config::Config;
+use crate::template::render_notification;
 
 use super::{
     Menu,
@@ -60,15 +62,21 @@ impl Menu for DeviceMenu {
 
                 match device.id.clone() {
                     Some(id) => {
-                        match Config::load() {
-                            Ok(mut config) => {
+                        match (Config::load_file(), Config::load()) {
+                            (Ok(mut config), Ok(loaded)) => {
                                 config.device_id = Some(id.clone());
+                                let template = loaded.notification_templates.unwrap_or_default().device;
 
                                 match config.store() {
                                     Ok(_) => {
                                         match self.client.transfer_playback(&id, Some(true)).await {
                                             Ok(_) => {
-                                                notify(&format!("Device set to {}", device.name), "", None)
+                                                let text = render_notification(&template, |key| {
+                                                    (key == "device").then(|| device.name.clone())
+                                                });
+                                                if let Some((summary, body)) = text {
+                                                    notify(&summary, &body, None)
+                                                }
                                             },
                                             Err(error) => {
                                                 enotify(&format!("Failed to switch playback to {}: {error}", device.name))
@@ -80,8 +88,8 @@ impl Menu for DeviceMenu {
 
                                 MenuResult::Exit
                             },
-                            Err(error) => {
-                                enotify(&format!("Failed to load config: {error}"));
+                            (Err(error), _) | (_, Err(error)) => {
+                                enotify_error("Failed to load config", &error);
                                 MenuResult::Back
                             },
                         }
@@ -93,8 +101,8 @@ impl Menu for DeviceMenu {
                 }
             }
             Err(error) => {
-                if error.kind().clone() != IntErrorKind::Empty {
-                    enotify(&format!("Failed to get index of selected item {selection:#?}: {error}"))
+                if *error.kind() != IntErrorKind::Empty {
+                    enotify_error(&format!("Failed to get index of selected item {selection:#?}"), &error)
                 }
                 MenuResult::Back
             }
@@ -116,7 +124,7 @@ pub async fn device_id(client: Arc<AuthCodePkceSpotify>, program: MenuProgram) -
                     match Config::load() {
                         Ok(config) => config.device_id,
                         Err(error) => {
-                            enotify(&format!("Failed to load device id from config: {error}"));
+                            enotify_error("Failed to load device id from config", &error);
                             None
                         }
                     }
@@ -124,7 +132,7 @@ pub async fn device_id(client: Arc<AuthCodePkceSpotify>, program: MenuProgram) -
             }
         },
         Err(error) => {
-            enotify(&format!("Failed to load device id from config: {error}"));
+            enotify_error("Failed to load device id from config", &error);
             None
         }
     }
@@ -1,38 +1,107 @@
 pub mod device;
 pub mod mode;
+pub mod pages;
 pub mod playback;
 pub mod search;
 
 use serde::{Serialize, Deserialize};
 use std::{
-    process::{Command, Stdio},
-    num::ParseIntError
+    io::Write,
+    process::{Command, ExitStatus, Stdio},
+    num::ParseIntError,
+    ops::RangeInclusive,
 };
 use async_trait::async_trait;
 
+/// What rofi exits with for its custom keys, Alt+1 and on by default.
+const ROFI_CUSTOM_KEY_CODES: RangeInclusive<i32> = 10..=28;
+
 #[async_trait]
-pub trait Menu {
+pub trait Menu: Send + Sync {
     fn items(&self) -> Vec<String>;
 
+    /// The items as `program` gets them, for menus that style their rows
+    /// where they can.
+    fn rows(&self, _program: &MenuProgram) -> Vec<String> {
+        self.items()
+    }
+
+    /// Arguments `program` needs to show [`Menu::rows`] the way they're
+    /// meant to look.
+    fn row_args(&self, _program: &MenuProgram) -> Vec<String> {
+        Vec::new()
+    }
+
     fn prompt(&self, program: MenuProgram, prompt_message: &str) -> String {
-        let input_from_echo = Command::new("echo")
-            .arg(self.items().join("\n"))
+        self.run(&program, program.command().args(["-p", prompt_message])).0
+    }
+
+    /// Like [`Menu::prompt`], starting with `filter` already typed in and
+    /// `message` shown under the prompt. Only rofi keeps the filter, shows
+    /// the message and has custom keys; with dmenu these are just empty.
+    fn prompt_with_filter(&self, program: MenuProgram, prompt_message: &str, filter: &str, message: &str) -> Prompt {
+        let mut command = program.command();
+        command.args(["-p", prompt_message]);
+
+        match program {
+            MenuProgram::Rofi => {
+                command.args(["-filter", filter, "-mesg", message, "-format", "s\nf"]);
+                let (output, status) = self.run(&program, &mut command);
+                // an empty filter gets trimmed off along with its newline
+                let (selection, filter) = output.split_once('\n').unwrap_or((&output, ""));
+
+                Prompt {
+                    selection: selection.to_string(),
+                    filter: filter.to_string(),
+                    custom_key: status.code()
+                        .filter(|code| ROFI_CUSTOM_KEY_CODES.contains(code))
+                        .map(|code| code - ROFI_CUSTOM_KEY_CODES.start() + 1),
+                }
+            },
+            MenuProgram::DMenu => Prompt {
+                selection: self.run(&program, &mut command).0,
+                filter: String::new(),
+                custom_key: None,
+            },
+        }
+    }
+
+    /// Runs `command` for `program` with the rows on its stdin, and returns
+    /// what it printed and how it exited.
+    fn run(&self, program: &MenuProgram, command: &mut Command) -> (String, ExitStatus) {
+        let mut child = command
+            .args(self.row_args(program))
+            .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
             .unwrap();
 
-        let selection = program.command()
-            .args(["-p", prompt_message])
-            .stdin(input_from_echo.stdout.unwrap())
-            .output()
+        // dropping stdin at the end of this statement closes the pipe so the
+        // menu program knows there are no more items
+        child.stdin
+            .take()
+            .unwrap()
+            .write_all(self.rows(program).join("\n").as_bytes())
             .unwrap();
 
-        String::from_utf8(selection.stdout).unwrap().trim().to_owned()
+        let selection = child.wait_with_output().unwrap();
+
+        (String::from_utf8(selection.stdout).unwrap().trim().to_owned(), selection.status)
     }
 
     async fn select(&self, program: MenuProgram) -> MenuResult;
 }
 
+/// What came back from [`Menu::prompt_with_filter`].
+pub struct Prompt {
+    pub selection: String,
+    /// What was typed to filter the items.
+    pub filter: String,
+    /// Which of rofi's custom keys closed the menu, if one did, counting
+    /// from 1 for Alt+1.
+    pub custom_key: Option<i32>,
+}
+
 pub enum MenuResult {
     Menu(Box<dyn Menu>),
     Back,
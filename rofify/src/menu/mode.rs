@@ -3,36 +3,31 @@ use std::{
     str::FromStr,
     sync::Arc
 };
-use futures::{
-    stream::TryStreamExt,
-    StreamExt
-};
 use async_trait::async_trait;
-use notify::enotify;
+use notify::{enotify, enotify_error};
+use serde::{Serialize, Deserialize};
 use rspotify::{
     prelude::*,
     AuthCodePkceSpotify,
     model::{
-        SearchType,
-        FullTrack
+        Page,
+        SearchType
     }
 };
-use strum::{
-    IntoEnumIterator,
-    EnumIter
-};
+use strum::EnumIter;
 use super::{
     Menu,
     MenuProgram,
     MenuResult,
     device::DeviceMenu,
     search::SearchMenu,
-    playback::PlaybackMenu
+    pages::Pager,
+    playback::{PlaybackMenu, play_uri}
 };
+use crate::config::{Config, ListLimits, ModeMenuSettings, Shortcut, ShortcutAction};
 
-const LIKED_SONGS_LIMIT: usize = 100;
-
-#[derive(Debug, EnumIter)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, EnumIter)]
+#[serde(rename_all = "snake_case")]
 pub enum Mode {
     ArtistSearch,
     AlbumSearch,
@@ -79,13 +74,39 @@ impl FromStr for Mode {
 }
 
 pub struct ModeMenu {
-    client: Arc<AuthCodePkceSpotify>
+    client: Arc<AuthCodePkceSpotify>,
+    settings: ModeMenuSettings,
+    limits: ListLimits,
+    /// Passed on to the menus opened from this one.
+    config: Arc<Config>,
 }
 
 impl ModeMenu {
-    pub fn new(client: Arc<AuthCodePkceSpotify>) -> ModeMenu {
+    pub fn new(client: Arc<AuthCodePkceSpotify>, config: Arc<Config>) -> ModeMenu {
         Self {
-            client
+            client,
+            settings: config.mode_menu.clone().unwrap_or_default(),
+            limits: config.list_limits.unwrap_or_default(),
+            config,
+        }
+    }
+
+    async fn search_menu(&self, search_type: SearchType) -> SearchMenu {
+        SearchMenu::new(Arc::clone(&self.client), search_type, Arc::clone(&self.config)).await
+    }
+
+    async fn open_shortcut(&self, shortcut: &Shortcut, program: MenuProgram) -> MenuResult {
+        match &shortcut.action {
+            ShortcutAction::Play(uri) => match play_uri(Arc::clone(&self.client), uri, program).await {
+                Ok(_) => MenuResult::Exit,
+                Err(error) => {
+                    enotify_error(&format!("Failed to play {}", shortcut.name), &error);
+                    MenuResult::Back
+                }
+            },
+            ShortcutAction::Search { search_type, query } => {
+                self.search_menu(*search_type).await.search(query).await
+            },
         }
     }
 }
@@ -93,57 +114,68 @@ impl ModeMenu {
 #[async_trait]
 impl Menu for ModeMenu {
     fn items(&self) -> Vec<String> {
-        Mode::iter().map(|mode| mode.to_string()).collect()
+        self.settings.shortcuts
+            .iter()
+            .map(|shortcut| shortcut.name.clone())
+            .chain(self.settings.modes().iter().map(|mode| mode.to_string()))
+            .collect()
     }
 
     async fn select(&self, program: MenuProgram) -> MenuResult {
-        let selection = self.prompt(program, "Rofify");
+        let selection = self.prompt(program.clone(), "Rofify");
+
+        // shortcuts come first, in case one is named after a mode
+        if let Some(shortcut) = self.settings.shortcuts.iter().find(|shortcut| shortcut.name == selection) {
+            return self.open_shortcut(shortcut, program).await;
+        }
+
         let parsed_mode = Mode::from_str(selection.as_str());
 
         match parsed_mode {
             Ok(mode) => match mode {
-                Mode::ArtistSearch => MenuResult::Menu(Box::new(
-                    SearchMenu::new(Arc::clone(&self.client), SearchType::Artist).await
-                )),
-                Mode::AlbumSearch => MenuResult::Menu(Box::new(
-                    SearchMenu::new(Arc::clone(&self.client), SearchType::Album).await
-                )),
-                Mode::TrackSearch => MenuResult::Menu(Box::new(
-                    SearchMenu::new(Arc::clone(&self.client), SearchType::Track).await
-                )),
-                Mode::PlaylistSearch => MenuResult::Menu(Box::new(
-                    SearchMenu::new(Arc::clone(&self.client), SearchType::Playlist).await
-                )),
+                Mode::ArtistSearch => MenuResult::Menu(Box::new(self.search_menu(SearchType::Artist).await)),
+                Mode::AlbumSearch => MenuResult::Menu(Box::new(self.search_menu(SearchType::Album).await)),
+                Mode::TrackSearch => MenuResult::Menu(Box::new(self.search_menu(SearchType::Track).await)),
+                Mode::PlaylistSearch => MenuResult::Menu(Box::new(self.search_menu(SearchType::Playlist).await)),
                 Mode::MyPlaylists => {
-                    match self.client
-                        .current_user_playlists()
-                        .try_collect::<Vec<_>>()
-                        .await {
-                            Ok(playlists) => MenuResult::Menu(Box::new(
-                                PlaybackMenu::new(Arc::clone(&self.client), playlists).await
-                            )),
-                            Err(error) => {
-                                enotify(&format!("Failed to get playlists: {error}"));
-                                MenuResult::Back
-                            }
+                    let client = Arc::clone(&self.client);
+                    let pager = Pager::new(Box::new(move |offset, limit| {
+                        let client = Arc::clone(&client);
+                        Box::pin(async move {
+                            client.current_user_playlists_manual(Some(limit), Some(offset)).await
+                        })
+                    }));
+
+                    match PlaybackMenu::paged(Arc::clone(&self.client), pager, self.limits.playlists, &self.config).await {
+                        Ok(menu) => MenuResult::Menu(Box::new(menu)),
+                        Err(error) => {
+                            enotify_error("Failed to get playlists", &error);
+                            MenuResult::Back
                         }
+                    }
                 },
                 Mode::LikedSongs => {
-                    let try_liked_songs = self.client
-                        .current_user_saved_tracks(None)
-                        .take(LIKED_SONGS_LIMIT)
-                        .try_filter_map(|saved_track| async {
-                            Ok(Some(saved_track.track))
+                    let client = Arc::clone(&self.client);
+                    let pager = Pager::new(Box::new(move |offset, limit| {
+                        let client = Arc::clone(&client);
+                        Box::pin(async move {
+                            let page = client.current_user_saved_tracks_manual(None, Some(limit), Some(offset)).await?;
+                            Ok(Page {
+                                href: page.href,
+                                items: page.items.into_iter().map(|saved_track| saved_track.track).collect(),
+                                limit: page.limit,
+                                next: page.next,
+                                offset: page.offset,
+                                previous: page.previous,
+                                total: page.total,
+                            })
                         })
-                        .try_collect::<Vec<FullTrack>>()
-                        .await;
+                    }));
 
-                    match try_liked_songs {
-                        Ok(liked_songs) => MenuResult::Menu(Box::new(
-                            PlaybackMenu::new(Arc::clone(&self.client), liked_songs).await
-                        )),
+                    match PlaybackMenu::paged(Arc::clone(&self.client), pager, self.limits.liked_songs, &self.config).await {
+                        Ok(menu) => MenuResult::Menu(Box::new(menu)),
                         Err(error) => {
-                            enotify(&format!("Failed to get liked songs: {error}"));
+                            enotify_error("Failed to get liked songs", &error);
                             MenuResult::Back
                         }
                     }
@@ -0,0 +1,230 @@
+//! Fetches lists from spotify a page at a time, for menus that show the
+//! start of a long list and load the rest on request.
+
+use std::cmp;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use rspotify::{ClientError, ClientResult};
+use rspotify::http::HttpError;
+use rspotify::model::Page;
+
+use crate::config::ListLimit;
+
+/// The most spotify hands out in one request.
+const MAX_PAGE_SIZE: u32 = 50;
+/// How many pages to have in flight at once when loading a lot of items.
+const CONCURRENT_PAGES: usize = 4;
+/// How many times to try a page again after spotify rate limits it.
+const RATE_LIMIT_RETRIES: u32 = 3;
+/// How long to wait after being rate limited when spotify doesn't say.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const TOO_MANY_REQUESTS: u16 = 429;
+
+/// Fetches the page of a list starting at an offset, with up to a limit of
+/// items.
+pub type FetchPage<T> = Box<dyn Fn(u32, u32) -> BoxFuture<'static, ClientResult<Page<T>>> + Send + Sync>;
+
+pub struct Pager<T> {
+    fetch: FetchPage<T>,
+    /// Where the next items start.
+    offset: u32,
+    /// How many items there are, once the first page has said.
+    total: Option<u32>,
+    /// How far into the list spotify lets requests go, if not to the end.
+    max_total: Option<u32>,
+}
+
+impl<T: Send + 'static> Pager<T> {
+    pub fn new(fetch: FetchPage<T>) -> Self {
+        Self {
+            fetch,
+            offset: 0,
+            total: None,
+            max_total: None,
+        }
+    }
+
+    /// Stops at `max_total` items, for lists like search results that
+    /// spotify won't page through past a point.
+    pub fn with_max_total(mut self, max_total: u32) -> Self {
+        self.max_total = Some(max_total);
+        self
+    }
+
+    pub fn has_more(&self) -> bool {
+        self.total.is_none_or(|total| self.offset < total)
+    }
+
+    /// Fetches the next `limit` items, the pages after the first one
+    /// concurrently.
+    pub async fn next(&mut self, limit: ListLimit) -> ClientResult<Vec<T>> {
+        let mut end = match limit {
+            ListLimit::Count(count) => self.offset.saturating_add(cmp::max(count, 1)),
+            ListLimit::All => u32::MAX,
+        };
+        let mut items = Vec::new();
+
+        // the first page says how long the list is
+        if self.total.is_none() {
+            let size = cmp::min(end - self.offset, MAX_PAGE_SIZE);
+            let page = fetch_page(&self.fetch, self.offset, size).await?;
+            let total = match self.max_total {
+                Some(max_total) => cmp::min(page.total, max_total),
+                None => page.total,
+            };
+
+            self.total = Some(total);
+            self.offset = cmp::min(self.offset + size, total);
+            items = page.items;
+        }
+
+        end = cmp::min(end, self.total.unwrap_or_default());
+        let ranges: Vec<(u32, u32)> = (self.offset..end)
+            .step_by(MAX_PAGE_SIZE as usize)
+            .map(|offset| (offset, cmp::min(MAX_PAGE_SIZE, end - offset)))
+            .collect();
+
+        let pages: Vec<Page<T>> = stream::iter(ranges)
+            .map(|(offset, size)| fetch_page(&self.fetch, offset, size))
+            .buffered(CONCURRENT_PAGES)
+            .try_collect()
+            .await?;
+
+        items.extend(pages.into_iter().flat_map(|page| page.items));
+        self.offset = cmp::max(self.offset, end);
+        Ok(items)
+    }
+}
+
+/// Fetches a page, waiting as long as spotify asks whenever it rate limits
+/// the request.
+async fn fetch_page<T>(fetch: &FetchPage<T>, offset: u32, limit: u32) -> ClientResult<Page<T>> {
+    let mut retries = 0;
+
+    loop {
+        match fetch(offset, limit).await {
+            Err(error) if retries < RATE_LIMIT_RETRIES => match retry_after(&error) {
+                Some(wait) => {
+                    retries += 1;
+                    tokio::time::sleep(wait).await;
+                },
+                None => return Err(error),
+            },
+            result => return result,
+        }
+    }
+}
+
+/// How long spotify wants to be left alone for, if `error` is it rate
+/// limiting a request.
+fn retry_after(error: &ClientError) -> Option<Duration> {
+    let response = match error {
+        ClientError::Http(error) => match error.as_ref() {
+            HttpError::StatusCode(response) if response.status().as_u16() == TOO_MANY_REQUESTS => response,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let seconds = response
+        .headers()
+        .get("retry-after")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok());
+    Some(seconds.map(Duration::from_secs).unwrap_or(DEFAULT_RETRY_AFTER))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use futures::FutureExt;
+
+    use super::*;
+
+    /// The offset and limit of each page fetched.
+    type Requests = Arc<Mutex<Vec<(u32, u32)>>>;
+
+    /// A pager over the numbers below `total`, and the pages it fetches.
+    fn numbers(total: u32) -> (Pager<u32>, Requests) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requested = Arc::clone(&requests);
+
+        let fetch: FetchPage<u32> = Box::new(move |offset, limit| {
+            requested.lock().unwrap().push((offset, limit));
+            let end = cmp::min(offset.saturating_add(limit), total);
+
+            async move {
+                Ok(Page {
+                    href: String::new(),
+                    items: (offset..end).collect(),
+                    limit,
+                    next: None,
+                    offset,
+                    previous: None,
+                    total,
+                })
+            }.boxed()
+        });
+        (Pager::new(fetch), requests)
+    }
+
+    #[tokio::test]
+    async fn loads_a_count_at_a_time() {
+        let (mut pager, _) = numbers(120);
+
+        assert_eq!(pager.next(ListLimit::Count(25)).await.unwrap(), (0..25).collect::<Vec<_>>());
+        assert!(pager.has_more());
+        assert_eq!(pager.next(ListLimit::Count(25)).await.unwrap(), (25..50).collect::<Vec<_>>());
+        assert_eq!(pager.next(ListLimit::All).await.unwrap(), (50..120).collect::<Vec<_>>());
+        assert!(!pager.has_more());
+        assert!(pager.next(ListLimit::All).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn all_loads_everything_in_full_pages() {
+        let (mut pager, requests) = numbers(120);
+
+        assert_eq!(pager.next(ListLimit::All).await.unwrap(), (0..120).collect::<Vec<_>>());
+        assert!(!pager.has_more());
+        assert_eq!(*requests.lock().unwrap(), vec![(0, 50), (50, 50), (100, 20)]);
+    }
+
+    #[tokio::test]
+    async fn counts_past_a_page_are_split_up() {
+        let (mut pager, requests) = numbers(1000);
+
+        assert_eq!(pager.next(ListLimit::Count(120)).await.unwrap().len(), 120);
+        assert_eq!(*requests.lock().unwrap(), vec![(0, 50), (50, 50), (100, 20)]);
+    }
+
+    #[tokio::test]
+    async fn a_count_of_zero_still_loads_one() {
+        let (mut pager, _) = numbers(10);
+
+        assert_eq!(pager.next(ListLimit::Count(0)).await.unwrap(), vec![0]);
+        assert!(pager.has_more());
+    }
+
+    #[tokio::test]
+    async fn stops_at_max_total() {
+        let (pager, requests) = numbers(5000);
+        let mut pager = pager.with_max_total(120);
+
+        assert_eq!(pager.next(ListLimit::All).await.unwrap().len(), 120);
+        assert!(!pager.has_more());
+        assert!(requests.lock().unwrap().iter().all(|(offset, limit)| offset + limit <= 120));
+    }
+
+    #[tokio::test]
+    async fn empty_lists_have_nothing_more() {
+        let (mut pager, requests) = numbers(0);
+
+        assert!(pager.has_more());
+        assert!(pager.next(ListLimit::Count(25)).await.unwrap().is_empty());
+        assert!(!pager.has_more());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
@@ -1,7 +1,7 @@
-use notify::enotify;
+use notify::enotify_error;
 use thiserror;
 use std::{
-    sync::Arc,
+    sync::{Arc, Mutex},
     result,
     num::IntErrorKind,
 };
@@ -10,14 +10,27 @@ use async_trait::async_trait;
 use rspotify::{
     prelude::*,
     AuthCodePkceSpotify,
+    ClientResult,
     model::{
+        parse_uri,
+        AlbumId,
+        ArtistId,
+        EpisodeId,
+        IdError,
+        PlaylistId,
+        ShowId,
+        TrackId,
+        Type,
         SimplifiedAlbum,
         FullTrack,
-        SimplifiedPlaylist, FullArtist,
+        SimplifiedPlaylist, FullArtist, SimplifiedArtist,
     },
 };
 
-use super::{Menu, MenuProgram, MenuResult, selection_index, device::device_id};
+use crate::config::{Config, ListLimit, RofiRows, RowFormats};
+use crate::template::{escape_markup, format_duration, release_year, render, render_styled};
+
+use super::{Menu, MenuProgram, MenuResult, selection_index, device::device_id, pages::Pager};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -25,55 +38,133 @@ pub enum Error {
     Client(#[from] rspotify::ClientError),
     #[error("no id found for {0}")]
     NoId(String),
+    #[error("invalid spotify URI {0:?}: {1}")]
+    Uri(String, IdError),
+    #[error("can't play {0}")]
+    Unplayable(String),
 }
 
 pub type Result<T> = result::Result<T, Error>;
 
+/// Rows that load more of a list into the menu it's in.
+const LOAD_MORE: &str = "Load more…";
+const LOAD_ALL: &str = "Load all";
+/// Rofi's keys for the same, which also work while the rows are filtered out.
+const LOAD_MORE_KEY: i32 = 1;
+const LOAD_ALL_KEY: i32 = 2;
+const LOAD_MESSAGE: &str = "Alt+1: load more, Alt+2: load all";
+/// How much of the secondary info shows through in markup rows.
+const DIMMED_ALPHA: &str = "60%";
+
 pub struct PlaybackMenu<T> {
     client: Arc<AuthCodePkceSpotify>,
-    items: Vec<T>
+    items: Mutex<Vec<T>>,
+    /// Fetches the rest of the list, if it didn't all fit in at first.
+    pager: Mutex<Option<Pager<T>>>,
+    limit: ListLimit,
+    formats: RowFormats,
+    rofi_rows: RofiRows,
 }
 
 impl<T> PlaybackMenu<T> {
-    pub async fn new(client: Arc<AuthCodePkceSpotify>, items: Vec<T>) -> PlaybackMenu<T> {
+    pub async fn new(client: Arc<AuthCodePkceSpotify>, items: Vec<T>, config: &Config) -> PlaybackMenu<T> {
+        let (formats, rofi_rows) = row_settings(config);
+
         Self {
             client,
-            items
+            items: Mutex::new(items),
+            pager: Mutex::new(None),
+            limit: ListLimit::All,
+            formats,
+            rofi_rows,
         }
     }
 }
 
-#[async_trait]
-impl<T: ListItem + StartPlayback + Send + Sync> Menu for PlaybackMenu<T> {
-    fn items(&self) -> Vec<String> {
-        self.items
+impl<T: ListItem + StartPlayback + Clone + Send + Sync + 'static> PlaybackMenu<T> {
+    /// A menu with the first `limit` items `pager` fetches, which loads
+    /// `limit` more at a time when asked.
+    pub async fn paged(
+        client: Arc<AuthCodePkceSpotify>,
+        mut pager: Pager<T>,
+        limit: ListLimit,
+        config: &Config
+    ) -> ClientResult<PlaybackMenu<T>> {
+        let items = pager.next(limit).await?;
+        let (formats, rofi_rows) = row_settings(config);
+
+        Ok(Self {
+            client,
+            items: Mutex::new(items),
+            pager: Mutex::new(Some(pager)),
+            limit,
+            formats,
+            rofi_rows,
+        })
+    }
+
+    /// A row for each item, followed by the rows for loading more if there
+    /// are more.
+    fn item_rows<F>(&self, row: F) -> Vec<String>
+    where
+        F: Fn(&T, usize) -> String
+    {
+        let mut rows: Vec<String> = self.items
+            .lock()
+            .unwrap()
             .iter()
             .enumerate()
-            .map(|(i, item)| item.list_item(i))
-            .collect()
+            .map(|(i, item)| row(item, i))
+            .collect();
+
+        if self.has_more() {
+            rows.extend([LOAD_MORE.to_string(), LOAD_ALL.to_string()]);
+        }
+        rows
     }
 
-    async fn select(&self, program: MenuProgram) -> MenuResult {
-        let selection = self.prompt(program.clone(), T::prompt_message().as_str());
-        let parsed_index = selection_index(&selection);
+    fn has_more(&self) -> bool {
+        self.pager.lock().unwrap().as_ref().is_some_and(Pager::has_more)
+    }
+
+    async fn load(&self, limit: ListLimit) -> ClientResult<()> {
+        // taken out for the fetch, since the lock can't be held across it
+        let mut pager = match self.pager.lock().unwrap().take() {
+            Some(pager) => pager,
+            None => return Ok(()),
+        };
+        let items = pager.next(limit).await;
+
+        *self.pager.lock().unwrap() = Some(pager);
+        self.items.lock().unwrap().extend(items?);
+        Ok(())
+    }
+
+    async fn play(&self, selection: &str, program: MenuProgram) -> MenuResult {
+        let parsed_index = selection_index(selection);
 
         match parsed_index {
             Ok(index) => {
-                let item = &self.items[index];
+                // cloned so the lock isn't held across starting playback
+                let item = self.items.lock().unwrap().get(index).cloned();
+                let item = match item {
+                    Some(item) => item,
+                    None => return MenuResult::Back,
+                };
 
                 match item.start_playback(Arc::clone(&self.client), program).await {
                     Ok(_) => {
                         MenuResult::Exit
                     },
                     Err(error) => {
-                        enotify(&format!("Failed to start playback: {error}"));
+                        enotify_error("Failed to start playback", &error);
                         MenuResult::Back
                     }
                 }
             }
             Err(error) => {
-                if error.kind().clone() != IntErrorKind::Empty {
-                    enotify(&format!("Failed to get index of selected item {selection:#?}: {error}"));
+                if *error.kind() != IntErrorKind::Empty {
+                    enotify_error(&format!("Failed to get index of selected item {selection:#?}"), &error);
                 };
                 MenuResult::Back
             }
@@ -81,10 +172,126 @@ impl<T: ListItem + StartPlayback + Send + Sync> Menu for PlaybackMenu<T> {
     }
 }
 
+#[async_trait]
+impl<T: ListItem + StartPlayback + Clone + Send + Sync + 'static> Menu for PlaybackMenu<T> {
+    fn items(&self) -> Vec<String> {
+        self.item_rows(|item, i| item.list_item(i, &self.formats))
+    }
+
+    fn rows(&self, program: &MenuProgram) -> Vec<String> {
+        match program {
+            MenuProgram::Rofi if self.rofi_rows.markup => {
+                self.item_rows(|item, i| item.markup_item(i, &self.formats))
+            },
+            _ => self.items(),
+        }
+    }
+
+    fn row_args(&self, program: &MenuProgram) -> Vec<String> {
+        if let MenuProgram::DMenu = program {
+            return Vec::new();
+        }
+
+        let mut args = Vec::new();
+
+        if self.rofi_rows.markup {
+            args.push("-markup-rows".to_string());
+        }
+        if let Some(columns) = &self.rofi_rows.display_columns {
+            args.extend([
+                "-display-columns".to_string(),
+                columns.clone(),
+                "-display-column-separator".to_string(),
+                self.rofi_rows.column_separator.clone(),
+            ]);
+        }
+        args
+    }
+
+    async fn select(&self, program: MenuProgram) -> MenuResult {
+        let mut filter = String::new();
+
+        loop {
+            let message = if self.has_more() { LOAD_MESSAGE } else { "" };
+            let prompt = self.prompt_with_filter(program.clone(), T::prompt_message().as_str(), &filter, message);
+
+            let limit = match (prompt.selection.as_str(), prompt.custom_key) {
+                (LOAD_MORE, _) | (_, Some(LOAD_MORE_KEY)) => self.limit,
+                (LOAD_ALL, _) | (_, Some(LOAD_ALL_KEY)) => ListLimit::All,
+                (selection, _) => return self.play(selection, program).await,
+            };
+
+            if let Err(error) = self.load(limit).await {
+                enotify_error("Failed to load more items", &error);
+            }
+            filter = prompt.filter;
+        }
+    }
+}
+
+fn row_settings(config: &Config) -> (RowFormats, RofiRows) {
+    (config.row_formats.clone().unwrap_or_default(), config.rofi_rows.clone().unwrap_or_default())
+}
+
+/// Plays a playlist, album, artist, show, track or episode by its URI.
+pub async fn play_uri(client: Arc<AuthCodePkceSpotify>, uri: &str, program: MenuProgram) -> Result<()> {
+    let invalid = |error| Error::Uri(uri.to_string(), error);
+    let (kind, id) = parse_uri(uri).map_err(invalid)?;
+    let device_id = device_id(Arc::clone(&client), program).await;
+
+    let playable = match kind {
+        Type::Track => Some(PlayableId::Track(TrackId::from_id(id).map_err(invalid)?)),
+        Type::Episode => Some(PlayableId::Episode(EpisodeId::from_id(id).map_err(invalid)?)),
+        _ => None,
+    };
+    if let Some(playable) = playable {
+        client.start_uris_playback([playable], device_id.as_deref(), None, None).await?;
+        return Ok(());
+    }
+
+    let context = match kind {
+        Type::Playlist => PlayContextId::Playlist(PlaylistId::from_id(id).map_err(invalid)?),
+        Type::Album => PlayContextId::Album(AlbumId::from_id(id).map_err(invalid)?),
+        Type::Artist => PlayContextId::Artist(ArtistId::from_id(id).map_err(invalid)?),
+        Type::Show => PlayContextId::Show(ShowId::from_id(id).map_err(invalid)?),
+        _ => return Err(Error::Unplayable(uri.to_string())),
+    };
+
+    client.start_context_playback(context, device_id.as_deref(), None, None).await?;
+    Ok(())
+}
+
 pub trait ListItem {
     fn prompt_message() -> String;
 
-    fn list_item(&self, index: usize) -> String;
+    /// The format for this kind of item out of `formats`.
+    fn row_format(formats: &RowFormats) -> &str;
+
+    /// The value of `key` in the item's row format.
+    fn placeholder(&self, key: &str) -> Option<String>;
+
+    /// The item's row, numbered with `index` so the selection can be found.
+    fn list_item(&self, index: usize, formats: &RowFormats) -> String {
+        format!("{}: {}", index, render(Self::row_format(formats), |key| self.placeholder(key)))
+    }
+
+    /// Like [`ListItem::list_item`] in pango markup, with the name in bold
+    /// and the rest of the placeholders dimmed.
+    fn markup_item(&self, index: usize, formats: &RowFormats) -> String {
+        let row = render_styled(
+            Self::row_format(formats),
+            |key| self.placeholder(key),
+            |key, text| {
+                let text = escape_markup(text);
+                match key {
+                    Some("name") => format!("<b>{text}</b>"),
+                    Some(_) => format!("<span alpha=\"{DIMMED_ALPHA}\">{text}</span>"),
+                    None => text,
+                }
+            }
+        );
+        format!("{}: {}", index, row)
+    }
 }
 
 #[async_trait]
@@ -97,12 +304,19 @@ impl ListItem for SimplifiedAlbum {
         "Select Album".to_string()
     }
 
-    fn list_item(&self, index: usize) -> String {
-        let artist_names: Vec<&str> = self.artists
-            .iter()
-            .map(|artist| artist.name.as_str())
-            .collect();
-        format!("{}: {} | {}", index, self.name, artist_names.join(", "))
+    fn row_format(formats: &RowFormats) -> &str {
+        &formats.album
+    }
+
+    fn placeholder(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "name" => self.name.clone(),
+            "artists" => artist_names(&self.artists),
+            "year" => year(self.release_date.as_deref()),
+            "album_type" => self.album_type.clone().unwrap_or_default(),
+            _ => return None,
+        };
+        Some(value)
     }
 }
 
@@ -131,12 +345,23 @@ impl ListItem for FullTrack {
         "Select Track".to_string()
     }
 
-    fn list_item(&self, index: usize) -> String {
-        let artist_names: Vec<&str> = self.artists
-            .iter()
-            .map(|artist| artist.name.as_str())
-            .collect();
-        format!("{}: {} | {} | {}", index, self.name, self.album.name, artist_names.join(", "))
+    fn row_format(formats: &RowFormats) -> &str {
+        &formats.track
+    }
+
+    fn placeholder(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "name" => self.name.clone(),
+            "artists" => artist_names(&self.artists),
+            "album" => self.album.name.clone(),
+            "duration" => format_duration(self.duration.num_milliseconds().max(0) as u64),
+            "year" => year(self.album.release_date.as_deref()),
+            "popularity" => self.popularity.to_string(),
+            "explicit" => if self.explicit { "E" } else { "" }.to_string(),
+            "track_number" => self.track_number.to_string(),
+            _ => return None,
+        };
+        Some(value)
     }
 }
 
@@ -165,13 +390,18 @@ impl ListItem for SimplifiedPlaylist {
         "Select Playlist".to_string()
     }
 
-    fn list_item(&self, index: usize) -> String {
-        let owner_name = match &self.owner.display_name {
-            Some(name) => name.clone(),
-            None => String::new()
-        };
+    fn row_format(formats: &RowFormats) -> &str {
+        &formats.playlist
+    }
 
-        format!("{}: {} | {}", index, self.name, owner_name)
+    fn placeholder(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "name" => self.name.clone(),
+            "owner" => self.owner.display_name.clone().unwrap_or_default(),
+            "tracks" => self.tracks.total.to_string(),
+            _ => return None,
+        };
+        Some(value)
     }
 }
 
@@ -195,8 +425,19 @@ impl ListItem for FullArtist {
         "Select Artist".to_string()
     }
 
-    fn list_item(&self, index: usize) -> String {
-        format!("{}: {}", index, self.name)
+    fn row_format(formats: &RowFormats) -> &str {
+        &formats.artist
+    }
+
+    fn placeholder(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "name" => self.name.clone(),
+            "followers" => self.followers.total.to_string(),
+            "popularity" => self.popularity.to_string(),
+            "genres" => self.genres.join(", "),
+            _ => return None,
+        };
+        Some(value)
     }
 }
 
@@ -214,3 +455,18 @@ impl StartPlayback for FullArtist {
     }
 }
 
+
+fn artist_names(artists: &[SimplifiedArtist]) -> String {
+    let names: Vec<&str> = artists
+        .iter()
+        .map(|artist| artist.name.as_str())
+        .collect();
+    names.join(", ")
+}
+
+fn year(release_date: Option<&str>) -> String {
+    release_date
+        .and_then(release_year)
+        .map(|year| year.to_string())
+        .unwrap_or_default()
+}
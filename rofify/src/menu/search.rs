@@ -1,11 +1,13 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use notify::enotify;
+use notify::{enotify, enotify_error};
 use rspotify::{
     prelude::*,
     AuthCodePkceSpotify,
+    ClientResult,
     model::{
+        Page,
         SearchType,
         SearchResult
     },
@@ -15,23 +17,61 @@ use super::{
     Menu,
     MenuProgram,
     MenuResult,
-    playback::PlaybackMenu
+    pages::{FetchPage, Pager},
+    playback::{ListItem, PlaybackMenu, StartPlayback}
 };
+use crate::config::Config;
 
-const SEARCH_LIMIT: u32 = 25;
+/// How far into the results spotify lets a search go.
+const MAX_SEARCH_RESULTS: u32 = 1000;
+/// The types of search there are result menus for.
+pub const SEARCH_TYPES: [SearchType; 4] = [
+    SearchType::Artist,
+    SearchType::Album,
+    SearchType::Track,
+    SearchType::Playlist,
+];
 
 pub struct SearchMenu {
     client: Arc<AuthCodePkceSpotify>,
     search_type: SearchType,
+    config: Arc<Config>,
 }
 
 impl SearchMenu {
-    pub async fn new(client: Arc<AuthCodePkceSpotify>, search_type: SearchType) -> SearchMenu {
+    pub async fn new(client: Arc<AuthCodePkceSpotify>, search_type: SearchType, config: Arc<Config>) -> SearchMenu {
         Self {
             client,
-            search_type
+            search_type,
+            config,
         }
     }
+
+    /// A menu with the first page of results for `query`, which `page` gets
+    /// out of each search result.
+    async fn results<T>(&self, query: &str, page: fn(SearchResult) -> Option<Page<T>>) -> ClientResult<MenuResult>
+    where
+        T: ListItem + StartPlayback + Clone + Send + Sync + 'static
+    {
+        let client = Arc::clone(&self.client);
+        let query = query.to_string();
+        let search_type = self.search_type;
+
+        let fetch: FetchPage<T> = Box::new(move |offset, limit| {
+            let client = Arc::clone(&client);
+            let query = query.clone();
+
+            Box::pin(async move {
+                let result = client.search(&query, search_type, None, None, Some(limit), Some(offset)).await?;
+                Ok(page(result).unwrap_or_else(empty_page))
+            })
+        });
+
+        let limit = self.config.list_limits.unwrap_or_default().search;
+        let pager = Pager::new(fetch).with_max_total(MAX_SEARCH_RESULTS);
+        let menu = PlaybackMenu::paged(Arc::clone(&self.client), pager, limit, &self.config).await?;
+        Ok(MenuResult::Menu(Box::new(menu)))
+    }
 }
 
 #[async_trait]
@@ -48,35 +88,56 @@ impl Menu for SearchMenu {
             return MenuResult::Back
         }
 
-        let result = self.client.search(
-            &query,
-            self.search_type,
-            None,
-            None,
-            Some(SEARCH_LIMIT),
-            None,
-        ).await;
+        self.search(&query).await
+    }
+}
+
+impl SearchMenu {
+    /// Searches for `query` and opens the results.
+    pub async fn search(&self, query: &str) -> MenuResult {
+        let result = match self.search_type {
+            SearchType::Artist => self.results(query, |result| match result {
+                SearchResult::Artists(page) => Some(page),
+                _ => None,
+            }).await,
+            SearchType::Album => self.results(query, |result| match result {
+                SearchResult::Albums(page) => Some(page),
+                _ => None,
+            }).await,
+            SearchType::Track => self.results(query, |result| match result {
+                SearchResult::Tracks(page) => Some(page),
+                _ => None,
+            }).await,
+            SearchType::Playlist => self.results(query, |result| match result {
+                SearchResult::Playlists(page) => Some(page),
+                _ => None,
+            }).await,
+            // shortcuts are checked for these when the config loads
+            search_type => {
+                enotify(&format!("Can't search for {}s", <&str>::from(search_type)));
+                Ok(MenuResult::Back)
+            },
+        };
 
         match result {
-            Ok(result) => match result {
-                SearchResult::Artists(page) => MenuResult::Menu(Box::new(
-                    PlaybackMenu::new(Arc::clone(&self.client), page.items).await
-                )),
-                SearchResult::Albums(page) => MenuResult::Menu(Box::new(
-                    PlaybackMenu::new(Arc::clone(&self.client), page.items).await
-                )),
-                SearchResult::Tracks(page) => MenuResult::Menu(Box::new(
-                    PlaybackMenu::new(Arc::clone(&self.client), page.items).await
-                )),
-                SearchResult::Playlists(page) => MenuResult::Menu(Box::new(
-                    PlaybackMenu::new(Arc::clone(&self.client), page.items).await
-                )),
-                _ => MenuResult::Exit
-            }
+            Ok(result) => result,
             Err(error) => {
-                enotify(&format!("Failed to get results for search {query:#?}: {error}"));
+                enotify_error(&format!("Failed to get results for search {query:#?}"), &error);
                 MenuResult::Back
             }
         }
     }
 }
+
+/// What a search answering with the wrong type of result counts as.
+fn empty_page<T>() -> Page<T> {
+    Page {
+        href: String::new(),
+        items: Vec::new(),
+        limit: 0,
+        next: None,
+        offset: 0,
+        previous: None,
+        total: 0,
+    }
+}
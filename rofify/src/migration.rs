@@ -0,0 +1,170 @@
+//! Upgrades config files written for older versions of rofify, one version
+//! at a time, keeping a copy of the file from before.
+
+use std::fs;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use notify::{dirs, notify};
+use serde_yaml::{Mapping, Value};
+
+use crate::config::{Error, Result};
+
+pub const CURRENT_VERSION: u32 = 1;
+const VERSION_KEY: &str = "version";
+
+/// Each upgrades a config from the version at its index to the next one.
+const MIGRATIONS: [fn(&mut Mapping); CURRENT_VERSION as usize] = [
+    // files from before versioning only need the version added
+    |_| (),
+];
+
+/// The text of the config file at `path`, upgraded to the current version
+/// first if it's older. Upgraded files are written back, with the old one
+/// backed up next to it.
+pub(crate) fn upgrade(path: &Path, text: String) -> Result<String> {
+    let (version, upgraded) = match migrate(path, &text)? {
+        Some(migrated) => migrated,
+        None => return Ok(text),
+    };
+
+    let backup = backup_path(path, version);
+    fs::copy(path, &backup)?;
+    dirs::write_atomically(path, upgraded.as_bytes())?;
+
+    notify(
+        "Config upgraded",
+        &format!("From version {version} to {CURRENT_VERSION}; the old file is at {}", backup.display()),
+        None
+    );
+    Ok(upgraded)
+}
+
+/// Like [`upgrade`], but leaves the file as it is.
+pub(crate) fn upgraded(path: &Path, text: String) -> Result<String> {
+    Ok(migrate(path, &text)?.map_or(text, |(_, upgraded)| upgraded))
+}
+
+/// The version of `text` and its upgraded text, or `None` if it's current.
+fn migrate(path: &Path, text: &str) -> Result<Option<(u32, String)>> {
+    let value: Value = serde_yaml::from_str(text).map_err(|source| Error::Malformed {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    // anything else fails to load as a config anyway, with a better error
+    let mut fields = match value {
+        Value::Mapping(fields) => fields,
+        _ => return Ok(None),
+    };
+
+    let version = version(&fields)?;
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+    if version > CURRENT_VERSION {
+        return Err(Error::TooNew(version, CURRENT_VERSION));
+    }
+
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(&mut fields);
+    }
+    fields.remove(VERSION_KEY);
+
+    // the version goes first, where it's easy to spot
+    let mut upgraded = Mapping::new();
+    upgraded.insert(VERSION_KEY.into(), CURRENT_VERSION.into());
+    upgraded.extend(fields);
+    Ok(Some((version, serde_yaml::to_string(&upgraded)?)))
+}
+
+/// Files from before versioning don't say, and count as version 0.
+fn version(fields: &Mapping) -> Result<u32> {
+    match fields.get(VERSION_KEY) {
+        None | Some(Value::Null) => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| Error::Invalid {
+                key: VERSION_KEY.to_string(),
+                message: "expected a whole number".to_string(),
+            }),
+    }
+}
+
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut backup = OsString::from(path.as_os_str());
+    backup.push(format!(".v{version}.bak"));
+    PathBuf::from(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::sink::SinkConfig;
+
+    /// A fresh config file holding `text`, in a directory of its own.
+    fn config_file(name: &str, text: &str) -> PathBuf {
+        notify::set_sinks(&SinkConfig::Silent, &SinkConfig::Silent);
+
+        let dir = std::env::temp_dir().join(format!("rofify-migration-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("config.yml");
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn unversioned_files_are_upgraded_and_backed_up() {
+        let text = "device_id: abc\n";
+        let path = config_file("unversioned", text);
+
+        let upgraded = upgrade(&path, text.to_string()).unwrap();
+
+        assert_eq!(upgraded, format!("version: {CURRENT_VERSION}\ndevice_id: abc\n"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), upgraded);
+        assert_eq!(fs::read_to_string(backup_path(&path, 0)).unwrap(), text);
+    }
+
+    #[test]
+    fn upgrading_in_memory_leaves_the_file_alone() {
+        let text = "device_id: abc\n";
+        let path = config_file("in-memory", text);
+
+        let upgraded = upgraded(&path, text.to_string()).unwrap();
+
+        assert_eq!(upgraded, format!("version: {CURRENT_VERSION}\ndevice_id: abc\n"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+        assert!(!backup_path(&path, 0).exists());
+    }
+
+    #[test]
+    fn current_files_are_left_alone() {
+        let text = format!("version: {CURRENT_VERSION}\ndevice_id: abc\n");
+        let path = config_file("current", &text);
+
+        assert_eq!(upgrade(&path, text.clone()).unwrap(), text);
+        assert!(!backup_path(&path, CURRENT_VERSION).exists());
+    }
+
+    #[test]
+    fn newer_files_are_refused() {
+        let text = format!("version: {}\n", CURRENT_VERSION + 1);
+        let path = config_file("newer", &text);
+
+        assert!(matches!(
+            upgrade(&path, text.clone()),
+            Err(Error::TooNew(version, CURRENT_VERSION)) if version == CURRENT_VERSION + 1
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+    }
+
+    #[test]
+    fn invalid_versions_are_refused() {
+        let text = "version: one\n";
+        let path = config_file("invalid", text);
+
+        assert!(matches!(upgrade(&path, text.to_string()), Err(Error::Invalid { .. })));
+    }
+}
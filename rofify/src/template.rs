@@ -0,0 +1,278 @@
+use notify::NotificationTemplate;
+
+/// Fills in each `{key}` in `template` with `value(key)`, laid out by
+/// `{key:<width.max}` if a [`Layout`] is given.
+///
+/// Keys `value` doesn't know about, and layouts that don't parse, are left
+/// in place so typos are easy to spot, and `{{`/`}}` produce literal braces.
+pub fn render<F>(template: &str, value: F) -> String
+where
+    F: Fn(&str) -> Option<String>
+{
+    render_styled(template, value, |_, text| text.to_string())
+}
+
+/// Like [`render`], passing each piece of the result through `style`: the
+/// text of a placeholder along with its key, and the text between
+/// placeholders with `None`.
+pub fn render_styled<F, S>(template: &str, value: F, style: S) -> String
+where
+    F: Fn(&str) -> Option<String>,
+    S: Fn(Option<&str>, &str) -> String
+{
+    let mut rendered = String::with_capacity(template.len());
+    // the text since the last placeholder, styled in one go
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            },
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            },
+            '{' => {
+                let mut key = String::new();
+                let mut closed = false;
+                for ch in chars.by_ref() {
+                    if ch == '}' {
+                        closed = true;
+                        break;
+                    }
+                    key.push(ch);
+                }
+                // an unclosed brace is just text
+                if !closed {
+                    literal.push('{');
+                    literal.push_str(&key);
+                    continue;
+                }
+
+                let (name, layout) = match key.split_once(':') {
+                    Some((name, layout)) => (name, Layout::parse(layout)),
+                    None => (key.as_str(), Some(Layout::default())),
+                };
+
+                match (value(name), layout) {
+                    (Some(text), Some(layout)) => {
+                        rendered.push_str(&style(None, &literal));
+                        rendered.push_str(&style(Some(name), &layout.apply(&text)));
+                        literal.clear();
+                    },
+                    _ => literal.push_str(&format!("{{{key}}}")),
+                }
+            },
+            _ => literal.push(ch),
+        }
+    }
+
+    rendered.push_str(&style(None, &literal));
+    rendered
+}
+
+/// Escapes `text` for pango markup, so names like `Simon & Garfunkel` show
+/// up as they are.
+pub fn escape_markup(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\'' => escaped.push_str("&apos;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// How a value is laid out, for lining up columns in monospace fonts:
+/// `<`, `>` or `^` aligns it left, right or centered in `width` characters,
+/// and `.max` cuts anything longer than `max` characters off with an
+/// ellipsis. `{name:<30.30}` makes a 30 character column, for example.
+#[derive(Default)]
+pub struct Layout {
+    align: Align,
+    width: usize,
+    max: Option<usize>,
+}
+
+#[derive(Default)]
+enum Align {
+    #[default]
+    Left,
+    Right,
+    Center,
+}
+
+impl Layout {
+    fn parse(layout: &str) -> Option<Self> {
+        let (align, rest) = match layout.chars().next() {
+            Some('<') => (Align::Left, &layout[1..]),
+            Some('>') => (Align::Right, &layout[1..]),
+            Some('^') => (Align::Center, &layout[1..]),
+            _ => (Align::Left, layout),
+        };
+        let (width, max) = match rest.split_once('.') {
+            Some((width, max)) => (width, Some(max.parse().ok()?)),
+            None => (rest, None),
+        };
+        let width = match width {
+            "" => 0,
+            width => width.parse().ok()?,
+        };
+
+        Some(Self { align, width, max })
+    }
+
+    fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        let length = text.chars().count();
+
+        if let Some(max) = self.max.filter(|&max| length > max) {
+            text = text.chars().take(max.saturating_sub(1)).collect();
+            if max > 0 {
+                text.push('…');
+            }
+        }
+
+        let padding = self.width.saturating_sub(text.chars().count());
+        let (left, right) = match self.align {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        format!("{}{text}{}", " ".repeat(left), " ".repeat(right))
+    }
+}
+
+/// Fills in a notification's summary and body, or `None` if the
+/// notification is turned off.
+pub fn render_notification<F>(template: &NotificationTemplate, value: F) -> Option<(String, String)>
+where
+    F: Fn(&str) -> Option<String>
+{
+    if !template.is_enabled() {
+        return None;
+    }
+
+    let summary = render(template.summary.as_deref().unwrap_or_default(), &value);
+    let body = render(template.body.as_deref().unwrap_or_default(), &value);
+    Some((summary, body))
+}
+
+/// Formats milliseconds as `m:ss`, or `h:mm:ss` for anything an hour or
+/// longer.
+pub fn format_duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+/// The year from a release date, which spotify gives as `YYYY`, `YYYY-MM`
+/// or `YYYY-MM-DD` depending on how precisely it's known.
+pub fn release_year(release_date: &str) -> Option<u32> {
+    release_date.get(..4)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(key: &str) -> Option<String> {
+        match key {
+            "name" => Some("Rofify".to_string()),
+            "empty" => Some(String::new()),
+            _ => None,
+        }
+    }
+
+    fn layout(spec: &str, text: &str) -> Option<String> {
+        Layout::parse(spec).map(|layout| layout.apply(text))
+    }
+
+    #[test]
+    fn fills_in_known_keys() {
+        assert_eq!(render("{name} is {empty}here", values), "Rofify is here");
+    }
+
+    #[test]
+    fn leaves_unknown_keys_in_place() {
+        assert_eq!(render("{name} {nmae}", values), "Rofify {nmae}");
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(render("{{name}} {{{name}}}", values), "{name} {Rofify}");
+    }
+
+    #[test]
+    fn unclosed_braces_are_left_as_written() {
+        assert_eq!(render("{name} {name", values), "Rofify {name");
+        assert_eq!(render("{", values), "{");
+    }
+
+    #[test]
+    fn lays_out_values() {
+        assert_eq!(render("[{name:<8}]", values), "[Rofify  ]");
+        assert_eq!(render("[{name:>8}]", values), "[  Rofify]");
+        assert_eq!(render("[{name:^9}]", values), "[ Rofify  ]");
+        assert_eq!(render("[{name:4}]", values), "[Rofify]");
+    }
+
+    #[test]
+    fn invalid_layouts_are_left_in_place() {
+        assert_eq!(render("{name:<wide}", values), "{name:<wide}");
+        assert_eq!(render("{name:.many}", values), "{name:.many}");
+        assert!(Layout::parse("<8.x").is_none());
+        assert!(Layout::parse("*8").is_none());
+    }
+
+    #[test]
+    fn max_cuts_off_with_an_ellipsis() {
+        assert_eq!(layout(".4", "Rofify").as_deref(), Some("Rof…"));
+        assert_eq!(layout(".6", "Rofify").as_deref(), Some("Rofify"));
+        assert_eq!(layout(">6.4", "Rofify").as_deref(), Some("  Rof…"));
+        assert_eq!(layout(".1", "Rofify").as_deref(), Some("…"));
+        assert_eq!(layout(".0", "Rofify").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn widths_count_characters_not_bytes() {
+        assert_eq!(layout("<4", "né").as_deref(), Some("né  "));
+    }
+
+    #[test]
+    fn styles_each_piece() {
+        let styled = render_styled("{name} & {nope}", values, |key, text| match key {
+            Some(key) => format!("<{key}>{text}</{key}>"),
+            None => escape_markup(text),
+        });
+        assert_eq!(styled, "<name>Rofify</name> &amp; {nope}");
+    }
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(escape_markup(r#"Simon & <Garfunkel> "'"#), "Simon &amp; &lt;Garfunkel&gt; &quot;&apos;");
+    }
+
+    #[test]
+    fn release_years() {
+        assert_eq!(release_year("1987"), Some(1987));
+        assert_eq!(release_year("1987-07"), Some(1987));
+        assert_eq!(release_year("1987-07-27"), Some(1987));
+        assert_eq!(release_year("87"), None);
+        assert_eq!(release_year(""), None);
+    }
+}